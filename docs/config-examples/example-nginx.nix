@@ -76,6 +76,9 @@
                 proxy_http_version 1.1;
                 '';
             };
+
+            # PK Let Plural Kitty see logouts so it forgets the tokens
+            "~ ^(/_matrix/client/[^/]*/logout)" = pkProxy;
           };
         };
       };
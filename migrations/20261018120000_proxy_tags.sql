@@ -0,0 +1 @@
+ALTER TABLE members ADD COLUMN IF NOT EXISTS proxy_tags TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS autoproxy TEXT NOT NULL DEFAULT 'front'
+    CHECK (autoproxy IN ('front', 'latch', 'off'));
+ALTER TABLE users ADD COLUMN IF NOT EXISTS latched_member TEXT;
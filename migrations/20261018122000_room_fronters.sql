@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS room_fronters (
+    mxid    TEXT,
+    room_id TEXT,
+    member  TEXT NOT NULL,
+    PRIMARY KEY (mxid, room_id),
+    FOREIGN KEY (mxid, member) REFERENCES members (mxid, name)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
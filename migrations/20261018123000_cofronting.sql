@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS fronters (
+    mxid        TEXT,
+    member      TEXT,
+    position    INTEGER NOT NULL,
+    PRIMARY KEY (mxid, member),
+    FOREIGN KEY (mxid, member) REFERENCES members (mxid, name)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+INSERT INTO fronters (mxid, member, position)
+    SELECT u.mxid, u.current_fronter, 0
+    FROM users AS u
+        JOIN members AS m ON u.mxid = m.mxid AND u.current_fronter = m.name
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE users DROP COLUMN IF EXISTS current_fronter;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS cofront_separator TEXT NOT NULL DEFAULT '&';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS cofront_avatar TEXT NOT NULL DEFAULT 'first'
+    CHECK (cofront_avatar IN ('first', 'account'));
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS active_rooms (
+    mxid        TEXT,
+    room_id     TEXT,
+    last_active TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (mxid, room_id)
+);
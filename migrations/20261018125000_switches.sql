@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS switches (
+    id          BIGSERIAL PRIMARY KEY,
+    mxid        TEXT NOT NULL,
+    members     TEXT[] NOT NULL,
+    started_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS switches_mxid_started_at ON switches (mxid, started_at);
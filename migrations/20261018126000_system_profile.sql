@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS system_name TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS system_tag TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS system_description TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS system_avatar TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS system_pronouns TEXT;
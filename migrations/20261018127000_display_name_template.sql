@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name_template TEXT;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS pronouns TEXT;
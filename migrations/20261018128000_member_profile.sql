@@ -0,0 +1,4 @@
+ALTER TABLE members ADD COLUMN IF NOT EXISTS description TEXT;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS color TEXT;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS birthday TEXT;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS notes TEXT;
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS groups (
+    mxid        TEXT,
+    name        TEXT,
+    description TEXT,
+    activators  TEXT[] NOT NULL DEFAULT '{}',
+    PRIMARY KEY (mxid, name)
+);
+
+CREATE TABLE IF NOT EXISTS group_members (
+    mxid        TEXT,
+    group_name  TEXT,
+    member      TEXT,
+    PRIMARY KEY (mxid, group_name, member),
+    FOREIGN KEY (mxid, group_name) REFERENCES groups (mxid, name)
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    FOREIGN KEY (mxid, member) REFERENCES members (mxid, name)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS pk_links (
+    mxid                    TEXT PRIMARY KEY,
+    token                   TEXT NOT NULL,
+    system_id               TEXT NOT NULL,
+    -- Switches started at or before this have already been pushed
+    switches_pushed_until   TIMESTAMPTZ,
+    last_synced             TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS pk_member_sync (
+    mxid        TEXT,
+    member      TEXT,
+    pk_id       TEXT NOT NULL,
+    synced_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- JSON of the member's fields on both sides as of `synced_at`, used to tell which side changed
+    snapshot    TEXT NOT NULL,
+    PRIMARY KEY (mxid, member),
+    UNIQUE (mxid, pk_id),
+    FOREIGN KEY (mxid, member) REFERENCES members (mxid, name)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
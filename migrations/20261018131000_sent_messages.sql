@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS sent_messages (
+    event_id    TEXT,
+    room_id     TEXT NOT NULL,
+    mxid        TEXT NOT NULL,
+    member      TEXT,
+    -- Order of the member among the message's co-fronters
+    position    BIGINT NOT NULL,
+    sent_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (event_id, member),
+    FOREIGN KEY (mxid, member) REFERENCES members (mxid, name)
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,4 @@
+-- A private member is hidden from other users entirely
+ALTER TABLE members ADD COLUMN IF NOT EXISTS private BOOLEAN NOT NULL DEFAULT false;
+-- Fields of the member hidden from other users, e.g. `pronouns`
+ALTER TABLE members ADD COLUMN IF NOT EXISTS private_fields TEXT[] NOT NULL DEFAULT '{}';
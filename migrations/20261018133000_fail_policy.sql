@@ -0,0 +1,3 @@
+-- NULL uses the server's default
+ALTER TABLE users ADD COLUMN IF NOT EXISTS fail_policy TEXT
+    CHECK (fail_policy IN ('fail-open', 'fail-closed', 'retry'));
@@ -12,7 +12,11 @@ use matrix_sdk::{
 };
 use tokio::time::sleep;
 
-use crate::{config::CONFIG, db::queries};
+use crate::{
+    config::CONFIG,
+    db::queries,
+    homeserver::{self, HOMESERVER},
+};
 
 pub static STARTED: AtomicBool = AtomicBool::new(false);
 
@@ -126,6 +130,9 @@ pub async fn create_client() -> anyhow::Result<Client> {
 #[tokio::main]
 pub async fn init() -> anyhow::Result<()> {
     let client = create_client().await.context("Error creating bot client")?;
+    if let Some(token) = client.access_token() {
+        homeserver::set_bot_token(token);
+    }
 
     // Update display name and avatar if needed
     if let Err(e) = update_account_info(&client.account()).await {
@@ -153,6 +160,10 @@ pub async fn init() -> anyhow::Result<()> {
     tracing::info!("Initial sync done");
     // DM message handler
     client.add_event_handler(commands::dm_handler);
+    // ❓ reactions on proxied messages
+    client.add_event_handler(commands::whois::reaction_handler);
+    // Periodic PluralKit sync
+    tokio::spawn(commands::pk::sync_daemon(client.clone()));
     // Auto join room bot is invited to
     client.add_event_handler(
         |room_member: StrippedRoomMemberEvent, client: Client, room: Room| async move {
@@ -222,7 +233,7 @@ async fn update_tracking_members() -> anyhow::Result<Vec<anyhow::Error>> {
 }
 
 async fn update_user_tracking_members(mxid: &str) -> anyhow::Result<()> {
-    let profile = queries::get_synapse_profile(mxid).await?;
+    let profile = HOMESERVER.profile(mxid).await?;
     queries::update_tracking_member(mxid, &profile)
         .await
         .with_context(|| format!("Error updating info for {mxid}"))?;
@@ -1,8 +1,20 @@
 #![allow(dead_code)] // Some of the framework copied from Emily is not currently in use
+mod autoproxy;
 mod clear;
+mod cofront;
+mod export;
+mod failpolicy;
+mod group;
+mod history;
 mod ignore;
+mod import;
 mod member;
+pub mod pk;
+mod room;
+mod stats;
+mod switch;
 mod system;
+pub mod whois;
 
 use std::future::Future;
 
@@ -26,7 +38,12 @@ const HELP: &str = r#"
 **Plural Kitty** is a tool that allows users to manage and switch identies on Matrix, similar to **Plural Kit** for Discord.
 This is alpha software so expect alpha quality. Only intended for use by testers at this time.
 > **Activators** are short case-sensitive strings of text that can be sent in this DM to switch members.
+> **Proxy tags** like `s:text` or `[text]` switch members for a single message when it is wrapped in them. They do not work in encrypted rooms.
 > **Ignoring** a room allows users to prevent Plural Kitty from changing avatar or displayname in certain rooms. 
+> **Autoproxy** decides who messages without a proxy tag are sent as: the current fronter (`front`), the last member picked by a proxy tag or activator (`latch`), or your account's own name and avatar (`off`).
+> **Room fronters** always front in a specific room, no matter who is fronting everywhere else.
+> **Co-fronting** lets several members front at once. Their names are joined, e.g. `Alex & Sam`, and the first fronter's avatar is used.
+> **Groups** organise members, a group's activator switches all of its members in at once.
 > **Tracking** allows users to set individual members to use the same avatar and displayname as the parent account.
 
 To get started: create a member, set an activator, and optionally a displayname and avatar. 
@@ -38,15 +55,53 @@ To get started: create a member, set an activator, and optionally a displayname
 - To clear an avatar send `!member [name] avatar !clear` or `!m [name] av !cl`<br>
 - Add an activator by sending `!member [name] activator add [string]` or `!m [name] act add [string]`
 - To remove an activator send `!member [name] activator remove [string]`or `!m [name] act rm [string]`<br>
+- Add a proxy tag by sending `!member [name] proxy add [tag]` or `!m [name] px add [tag]`, e.g. `!m sasha px add s:text`
+- To remove a proxy tag send `!member [name] proxy remove [tag]` or `!m [name] px rm [tag]`<br>
+- Create a group by sending `!group new [name]` or `!g new [name]`, and list groups with `!group` or `!g`
+- Add or remove group members by sending `!group [name] add [member...]` or `!g [name] rm [member...]`
+- Rename a group with `!group [name] rename [new name]`, set its description with `!g [name] desc [text]`, and delete it with `!g [name] delete`
+- Switch a whole group in with an activator by sending `!group [name] activator add [string]` or `!g [name] act add [string]`
+- Show a group's members and activators by sending `!group [name] show` or `!g [name] sh`<br>
 - Toggle ignoring a room by sending `!ignore [room id]` or `!i [room alias]`
 - List ignored rooms by sending `!ignore` or `!i` by itself<br>
+- Make a member always front in a room by sending `!room [room] front [name]` or `!r [room] f [name]`
+- To clear a room's fronter send `!room [room] front !clear` or `!r [room] f !cl`<br>
 - Toggle tracking for displayname *and* avatar by sending `!member [name] trackaccount` or `!m [name] ta`
 - To toggle tracking for a member's displayname, send `!member [name] displayname !acc`
 - To toggle tracking for a member's avatar, send `!member [name] avatar !acc`<br>
+- Set a member's pronouns, description, colour, birthday, or notes by sending `!member [name] [pronouns|desc|color|birthday|notes] [text]` or `!m [name] [pn|desc|color|bd|notes] [text]`
+- To clear one of them send `!member [name] [field] !clear`, e.g. `!m sasha pn !cl`<br>
 - Show info on an individual member send `!member [name] show` or `!m [name] sh`
-- List all system members, activators, and current fronter by sending `!system` or `!s`
+- Show the display name a member will get after templating by sending `!member [name] preview` or `!m [name] pv`
+- Hide a member from other users by sending `!member [name] privacy private` or `!m [name] priv private`, and show them again with `public`
+- Hide one of a member's fields by sending `!member [name] privacy [displayname|avatar|pronouns|desc|color|birthday] private`, and check a member's privacy with `!m [name] priv`
+- List all system members, activators, and current fronters by sending `!system` or `!s`
+- List only the members of a group by sending `!system [group]` or `!s [group]`
+- See another user's system and public members by sending `!system @user:server` or `!s @user:server`
+- Set your system's name, tag, description, or pronouns by sending `!system set [name|tag|description|pronouns] [text]`, or `!clear` to clear it
+- Set a display name template by sending `!system set template [template]`, e.g. `{name} ({pronouns}) | {tag}`. `{name}`, `{pronouns}`, `{tag}`, and `{system}` are filled in
+- Set your system's avatar by sending `!system set avatar [mxc url]` or `!s set avatar` *in reply* to an image. Members without an avatar use it
 - Switch a member to front by sending a valid activator - E.g. ` --ursa, a, <<, :hh `
+- Switch several members to front by sending their activators together, e.g. `s u`
+- Switch members to front by name by sending `!switch [name...]` or `!sw [name...]`, use quotes for names with spaces
+- Clear the current fronters by sending `!switch out` or `!sw out`
+- Show recent switches and how long they lasted by sending `!history [n]` or `!hi [n]`
+- Show front time per member by sending `!stats [day|week|month]` or `!st [d|w|m]`, add `csv` to also get a CSV file
+- Set how co-fronting names are joined by sending `!cofront separator [text]` or `!cf sep [text]`, e.g. `!cf sep &`
+- Set the co-fronting avatar by sending `!cofront avatar [first|account]` or `!cf av [first|account]`
 - Clear the current member from front by sending `!clear` or `!cl`<br>
+- Set the autoproxy mode by sending `!autoproxy [front|latch|off]` or `!ap [front|latch|off]`
+- Show the current autoproxy mode by sending `!autoproxy` or `!ap` by itself<br>
+- Choose what happens to a message when Plural Kitty can't set your member's name and avatar in the room first by sending `!failpolicy [fail-open|fail-closed|retry]` or `!fp [...]`. `fail-open` sends it anyway, `fail-closed` doesn't send it, and `retry` tries again before not sending it
+- Go back to the server's default with `!failpolicy default`, and show your fail policy with `!failpolicy` or `!fp` by itself<br>
+- Export your system, members, groups, switches, and settings as a file by sending `!export`
+- Import a Plural Kitty, PluralKit, Tupperbox or Simply Plural export by sending the `.json` file here, then replying to it with `!import`
+- Add `dry` to only see what would be imported, or `overwrite` to replace members that already exist, e.g. `!import overwrite`<br>
+- Link your PluralKit system by sending `!pk link [token]`, get the token by sending `pk;token` to PluralKit. Members are then pulled from PluralKit and new switches pushed to it regularly
+- Sync with PluralKit right away by sending `!pk sync`, show the link's status with `!pk`, and unlink with `!pk unlink`
+- Members changed in both places keep their Plural Kitty values, `!pk sync` lists those conflicts<br>
+- Find out which member sent a message by sending `!whois [message link]` or `!w [message link]`, this works for messages sent through Plural Kitty in rooms you are in
+- Or react to the message with ❓ in a room Plural Kitty is in, and it will DM you who sent it
 - Show this help message again by sending `!help` or `!h`
 ### Example of setting up a new member.
 ```
@@ -102,11 +157,31 @@ pub async fn dm_handler(
                 if word.starts_with('!') {
                     match word.as_str() {
                         "!member" | "!m" => handler.run(member::exec(cmd, &room, &event)).await,
-                        "!system" | "!s" => handler.run(system::exec(&room, &event.sender)).await,
+                        "!system" | "!s" => handler.run(system::exec(cmd, &room, &event)).await,
+                        "!group" | "!g" => handler.run(group::exec(cmd, &room, &event)).await,
                         "!ignore" | "!i" => {
                             handler.run(ignore::exec(cmd, &room, &client, &event)).await
                         }
+                        "!room" | "!r" => {
+                            handler.run(room::exec(cmd, &room, &client, &event)).await
+                        }
                         "!clear" | "!cl" => handler.run(clear::exec(&room, &event)).await,
+                        "!switch" | "!sw" => handler.run(switch::exec(cmd, &room, &event)).await,
+                        "!export" => handler.run(export::exec(&room, &event)).await,
+                        "!import" => handler.run(import::exec(cmd, &room, &event)).await,
+                        "!pk" => handler.run(pk::exec(cmd, &room, &client, &event)).await,
+                        "!whois" | "!w" => {
+                            handler.run(whois::exec(cmd, &room, &client, &event)).await
+                        }
+                        "!history" | "!hi" => handler.run(history::exec(cmd, &room, &event)).await,
+                        "!stats" | "!st" => handler.run(stats::exec(cmd, &room, &event)).await,
+                        "!cofront" | "!cf" => handler.run(cofront::exec(cmd, &room, &event)).await,
+                        "!autoproxy" | "!ap" => {
+                            handler.run(autoproxy::exec(cmd, &room, &event)).await
+                        }
+                        "!failpolicy" | "!fp" => {
+                            handler.run(failpolicy::exec(cmd, &room, &event)).await
+                        }
                         "!help" | "!h" => handler.run_no_feddback(help(cmd, &room)).await,
                         _ => {
                             let content = RoomMessageEventContent::text_markdown(
@@ -115,24 +190,30 @@ pub async fn dm_handler(
                             room.send(content, None).await?;
                         }
                     }
-                } else if let Some(name) =
-                    queries::set_fronter_from_activator(event.sender.as_str(), &word.to_lowercase())
-                        .await
-                        .context("Error updating current member")?
-                {
-                    room.send(
-                        RoomMessageEventContent::text_markdown(format!(
-                            "Current fronter set to **{name}**"
-                        )),
-                        None,
-                    )
-                    .await?;
                 } else {
-                    let msg = format!("Unknown command or activator.\n\n{HELP}");
-                    room.send(RoomMessageEventContent::text_markdown(msg), None)
-                        .await?;
+                    let mut activators = vec![word.to_lowercase()];
+                    while let Some(word) = cmd.pop_word() {
+                        activators.push(word.to_lowercase());
+                    }
+                    if let Some(activated) =
+                        queries::get_members_from_activators(event.sender.as_str(), &activators)
+                            .await
+                            .context("Error looking up activators")?
+                    {
+                        handler
+                            .run(switch::activate(activated, &room, &event.sender))
+                            .await;
+                    } else {
+                        let msg = format!("Unknown command or activator.\n\n{HELP}");
+                        room.send(RoomMessageEventContent::text_markdown(msg), None)
+                            .await?;
+                    }
                 }
             }
+        } else if let MessageType::File(file) = &event.content.msgtype {
+            if file.body.ends_with(".json") {
+                handler.run(import::exec_file(&room, &event, file)).await;
+            }
         }
     }
     Ok(())
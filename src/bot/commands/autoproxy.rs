@@ -0,0 +1,37 @@
+use anyhow::Context;
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+
+use crate::bot::parser::Cmd;
+use crate::db::models::AutoproxyMode;
+use crate::db::queries;
+
+use super::ErrList;
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let user = event.sender.as_str();
+    let msg = match cmd.pop_word() {
+        Some(mode) => {
+            let mode: AutoproxyMode = mode.to_lowercase().parse()?;
+            queries::create_user(user).await?;
+            queries::set_autoproxy(user, mode)
+                .await
+                .context("Error setting autoproxy mode")?;
+            format!("Autoproxy set to **{mode}**")
+        }
+        None => {
+            let mode = queries::get_autoproxy(user).await?;
+            format!("Autoproxy is currently **{mode}**")
+        }
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
@@ -4,14 +4,17 @@ use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
 
 use crate::db::queries;
 
-use super::ErrList;
+use super::{switch, ErrList};
 
 pub async fn exec(room: &Joined, event: &OriginalSyncRoomMessageEvent) -> anyhow::Result<ErrList> {
-    queries::set_current_fronter(event.sender.as_str(), None).await?;
+    queries::clear_fronters(event.sender.as_str()).await?;
     room.send(
-        RoomMessageEventContent::text_markdown("**Cleared current fronter**"),
+        RoomMessageEventContent::text_markdown(
+            "**Cleared current fronter**\n\nRooms will go back to your account's name and avatar",
+        ),
         None,
     )
     .await?;
+    switch::spawn_rollout(room.clone(), event.sender.clone());
     Ok(vec![])
 }
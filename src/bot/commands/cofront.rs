@@ -0,0 +1,61 @@
+use anyhow::{anyhow, bail, Context};
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+
+use crate::bot::parser::Cmd;
+use crate::db::models::CofrontAvatar;
+use crate::db::queries;
+use crate::proxy::identity::join_names;
+
+use super::ErrList;
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let user = event.sender.as_str();
+    let msg = match cmd.pop_word().as_deref() {
+        None => {
+            let settings = queries::get_cofront_settings(user).await?;
+            format!(
+                "Co-fronting names look like `{}` and use the `{}` avatar",
+                join_names(&["Alex", "Sam"], &settings.separator),
+                settings.avatar,
+            )
+        }
+        Some("separator" | "sep") => {
+            let separator = cmd.into_string();
+            if separator.is_empty() {
+                bail!("Please give a separator, e.g. `!cofront separator &`");
+            }
+            queries::create_user(user).await?;
+            queries::set_cofront_separator(user, &separator)
+                .await
+                .context("Error setting co-fronting separator")?;
+            format!(
+                "Co-fronting names will look like `{}`",
+                join_names(&["Alex", "Sam"], &separator)
+            )
+        }
+        Some("avatar" | "av") => {
+            let avatar: CofrontAvatar = cmd
+                .pop_word()
+                .ok_or_else(|| anyhow!("Please give an avatar rule, `first` or `account`"))?
+                .to_lowercase()
+                .parse()?;
+            queries::create_user(user).await?;
+            queries::set_cofront_avatar(user, avatar)
+                .await
+                .context("Error setting co-fronting avatar")?;
+            format!("Co-fronting will use the `{avatar}` avatar")
+        }
+        Some(s) => bail!("Unkown command {s}"),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
@@ -0,0 +1,280 @@
+//! Exports everything Plural Kitty knows about a system as a JSON file.
+//!
+//! The file follows the layout of PluralKit's `pk;export` where the two overlap, so it can be
+//! imported there too. Anything only Plural Kitty has lives in the `plural_kitty` section, or in
+//! extra member and group fields PluralKit ignores.
+
+use anyhow::{bail, Context};
+use matrix_sdk::attachment::AttachmentConfig;
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::Member;
+use crate::db::queries;
+use crate::proxy::tags::ProxyTag;
+
+use super::ErrList;
+
+/// Version of the `plural_kitty` section, bump it when its layout changes.
+pub const EXPORT_VERSION: u32 = 3;
+/// Version of PluralKit's export layout the rest of the file follows.
+const PLURALKIT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportFile {
+    pub version: u32,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub tag: Option<String>,
+    pub pronouns: Option<String>,
+    pub avatar_url: Option<String>,
+    pub members: Vec<ExportMember>,
+    pub groups: Vec<ExportGroup>,
+    pub switches: Vec<ExportSwitch>,
+    pub plural_kitty: PluralKittyData,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportMember {
+    /// The member's name, PluralKit only uses it to link switches and groups to members
+    pub id: String,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub pronouns: Option<String>,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    /// `YYYY-MM-DD`, with the year `0004` if it isn't known like PluralKit does
+    pub birthday: Option<String>,
+    pub avatar_url: Option<String>,
+    pub proxy_tags: Vec<ExportProxyTag>,
+    pub activators: Vec<String>,
+    pub track_account: bool,
+    pub notes: Option<String>,
+    /// Missing from exports made before privacy settings existed
+    #[serde(default)]
+    pub privacy: ExportPrivacy,
+}
+
+impl ExportMember {
+    pub fn from_member(member: Member) -> Self {
+        let privacy = ExportPrivacy::from_member(&member);
+        ExportMember {
+            id: member.name.clone(),
+            name: member.name,
+            display_name: member.display_name,
+            pronouns: member.pronouns,
+            description: member.description,
+            color: member.color,
+            birthday: member.birthday.map(|birthday| match birthday.len() {
+                5 => format!("0004-{birthday}"),
+                _ => birthday,
+            }),
+            avatar_url: member.avatar,
+            proxy_tags: member
+                .proxy_tags
+                .iter()
+                .filter_map(|tag| ProxyTag::parse(tag))
+                .map(|tag| ExportProxyTag {
+                    prefix: Some(tag.prefix.to_owned()).filter(|p| !p.is_empty()),
+                    suffix: Some(tag.suffix.to_owned()).filter(|s| !s.is_empty()),
+                })
+                .collect(),
+            activators: member.activators,
+            track_account: member.track_account,
+            privacy,
+            notes: member.notes,
+        }
+    }
+}
+
+/// PluralKit's member privacy settings that Plural Kitty has an equivalent for, plus the fields
+/// only Plural Kitty can hide. Each is `public` or `private`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ExportPrivacy {
+    pub visibility: Option<String>,
+    pub description_privacy: Option<String>,
+    pub birthday_privacy: Option<String>,
+    pub pronoun_privacy: Option<String>,
+    pub avatar_privacy: Option<String>,
+    pub display_name_privacy: Option<String>,
+    pub color_privacy: Option<String>,
+}
+
+impl ExportPrivacy {
+    fn fields(&self) -> [(&'static str, &Option<String>); 6] {
+        [
+            ("description", &self.description_privacy),
+            ("birthday", &self.birthday_privacy),
+            ("pronouns", &self.pronoun_privacy),
+            ("avatar", &self.avatar_privacy),
+            ("displayname", &self.display_name_privacy),
+            ("color", &self.color_privacy),
+        ]
+    }
+
+    pub fn from_member(member: &Member) -> Self {
+        let level = |private: bool| Some(if private { "private" } else { "public" }.to_owned());
+        let private = |field: &str| level(member.private_fields.iter().any(|f| f == field));
+        ExportPrivacy {
+            visibility: level(member.private),
+            description_privacy: private("description"),
+            birthday_privacy: private("birthday"),
+            pronoun_privacy: private("pronouns"),
+            avatar_privacy: private("avatar"),
+            display_name_privacy: private("displayname"),
+            color_privacy: private("color"),
+        }
+    }
+
+    /// Copies the settings to `member`, settings that aren't given are left public.
+    pub fn apply(&self, member: &mut Member) {
+        let private = |level: &Option<String>| level.as_deref() == Some("private");
+        member.private = private(&self.visibility);
+        member.private_fields = self
+            .fields()
+            .into_iter()
+            .filter(|(_, level)| private(level))
+            .map(|(field, _)| field.to_owned())
+            .collect();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportProxyTag {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportGroup {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Member IDs
+    pub members: Vec<String>,
+    pub activators: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportSwitch {
+    /// RFC 3339
+    pub timestamp: String,
+    /// Member IDs
+    pub members: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PluralKittyData {
+    pub version: u32,
+    pub display_name_template: Option<String>,
+    pub autoproxy: String,
+    pub cofront_separator: String,
+    pub cofront_avatar: String,
+    pub ignored_rooms: Vec<String>,
+    pub room_fronters: Vec<ExportRoomFronter>,
+    /// `None` for the server's default. Missing from exports made before fail policies existed
+    #[serde(default)]
+    pub fail_policy: Option<String>,
+    #[serde(default)]
+    pub latched_member: Option<String>,
+    /// Who was fronting when the export was made, in order
+    #[serde(default)]
+    pub fronters: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportRoomFronter {
+    pub room_id: String,
+    pub member: String,
+}
+
+pub async fn exec(room: &Joined, event: &OriginalSyncRoomMessageEvent) -> anyhow::Result<ErrList> {
+    let user = event.sender.as_str();
+    if !queries::user_exists(user).await? {
+        bail!("There is nothing to export yet. Create a member using `!member new [name]`");
+    }
+    let export = build(user).await.context("Error gathering export")?;
+    let data = serde_json::to_vec_pretty(&export).context("Error serialising export")?;
+    room.send_attachment(
+        "plural-kitty-export.json",
+        &mime::APPLICATION_JSON,
+        data,
+        AttachmentConfig::new(),
+    )
+    .await
+    .context("Error sending export file")?;
+    room.send(
+        RoomMessageEventContent::text_markdown(
+            "Here is your export. To restore it, send the file here and reply to it with `!import`",
+        ),
+        None,
+    )
+    .await
+    .context("Error sending reply")?;
+    Ok(vec![])
+}
+
+async fn build(user: &str) -> anyhow::Result<ExportFile> {
+    let system = queries::get_system(user).await?;
+    let mut members = vec![];
+    for name in queries::list_members(user).await? {
+        let member = queries::get_member(user, &name).await?;
+        members.push(ExportMember::from_member(member));
+    }
+    let mut groups = vec![];
+    for name in queries::list_groups(user).await? {
+        let group = queries::get_group(user, &name).await?;
+        groups.push(ExportGroup {
+            id: group.name.clone(),
+            members: queries::get_group_members(user, &name).await?,
+            name: group.name,
+            description: group.description,
+            activators: group.activators,
+        });
+    }
+    let switches = queries::get_all_switches(user)
+        .await?
+        .into_iter()
+        .map(|(members, timestamp)| ExportSwitch { timestamp, members })
+        .collect();
+    let cofront = queries::get_cofront_settings(user).await?;
+    let room_fronters = queries::list_room_fronters(user)
+        .await?
+        .into_iter()
+        .map(|(room_id, member)| ExportRoomFronter { room_id, member })
+        .collect();
+    Ok(ExportFile {
+        version: PLURALKIT_VERSION,
+        name: system.name,
+        description: system.description,
+        tag: system.tag,
+        pronouns: system.pronouns,
+        avatar_url: system.avatar,
+        members,
+        groups,
+        switches,
+        plural_kitty: PluralKittyData {
+            version: EXPORT_VERSION,
+            display_name_template: system.template,
+            autoproxy: queries::get_autoproxy(user).await?.to_string(),
+            cofront_separator: cofront.separator,
+            cofront_avatar: cofront.avatar.to_string(),
+            ignored_rooms: queries::list_ignored(user).await?,
+            room_fronters,
+            fail_policy: queries::get_fail_policy(user)
+                .await?
+                .map(|policy| policy.to_string()),
+            latched_member: queries::get_latched_member(user)
+                .await?
+                .map(|member| member.name),
+            fronters: queries::get_current_fronters(user)
+                .await?
+                .into_iter()
+                .map(|member| member.name)
+                .collect(),
+        },
+    })
+}
@@ -0,0 +1,51 @@
+use anyhow::Context;
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+
+use crate::bot::parser::Cmd;
+use crate::config::CONFIG;
+use crate::db::models::FailPolicy;
+use crate::db::queries;
+
+use super::ErrList;
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let user = event.sender.as_str();
+    let msg = match cmd.pop_word().map(|word| word.to_lowercase()) {
+        Some(word) if word == "default" => {
+            queries::create_user(user).await?;
+            queries::set_fail_policy(user, None)
+                .await
+                .context("Error setting fail policy")?;
+            format!(
+                "Fail policy set to the server's default, **{}**",
+                CONFIG.fail_policy
+            )
+        }
+        Some(policy) => {
+            let policy: FailPolicy = policy.parse()?;
+            queries::create_user(user).await?;
+            queries::set_fail_policy(user, Some(policy))
+                .await
+                .context("Error setting fail policy")?;
+            format!("Fail policy set to **{policy}**")
+        }
+        None => match queries::get_fail_policy(user).await? {
+            Some(policy) => format!("Fail policy is currently **{policy}**"),
+            None => format!(
+                "Fail policy is currently the server's default, **{}**",
+                CONFIG.fail_policy
+            ),
+        },
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, bail, Context};
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::UserId;
+
+use crate::bot::parser::Cmd;
+use crate::db::queries;
+
+use super::ErrList;
+
+/// Words that can't be group names because `!group` or `!system [group]` would read them as a
+/// subcommand.
+const RESERVED_NAMES: &[&str] = &["new", "list", "ls", "set"];
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let user = &event.sender;
+    match cmd.pop_word().as_deref() {
+        None | Some("list" | "ls") => list_groups(room, user).await?,
+        Some("new") => new_group(cmd, room, user).await?,
+        Some(name) => {
+            if !queries::group_exists(user.as_str(), name).await? {
+                bail!("Group {name} does not exist.\n\nCreate this group with `!g new {name}`");
+            }
+            let sub_command = cmd
+                .pop_word()
+                .ok_or_else(|| anyhow!("Please specify a subcommand"))?;
+            match sub_command.as_str() {
+                "add" => add_members(cmd, room, user, name).await?,
+                "remove" | "rm" => remove_members(cmd, room, user, name).await?,
+                "rename" => rename_group(cmd, room, user, name).await?,
+                "description" | "desc" => set_description(cmd, room, user, name).await?,
+                "activator" | "act" => activator_cmd(cmd, room, user, name).await?,
+                "show" | "sh" => show_group(room, user, name).await?,
+                "delete" => remove_group(room, user, name).await?,
+                s => bail!("Unkown command {s}"),
+            }
+        }
+    }
+    Ok(vec![])
+}
+
+async fn new_group(mut cmd: Cmd, room: &Joined, user: &UserId) -> anyhow::Result<()> {
+    let name = cmd.pop_word().ok_or_else(|| anyhow!("Give name plz"))?;
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        bail!("`{name}` can't be used as a group name");
+    }
+    queries::create_user(user.as_str()).await?;
+    queries::create_group(user.as_str(), &name).await?;
+    room.send(
+        RoomMessageEventContent::text_markdown(format!(
+            "Created group `{name}`\n\nAdd members to it with `!g {name} add [member...]`"
+        )),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn list_groups(room: &Joined, user: &UserId) -> anyhow::Result<()> {
+    let groups = queries::list_groups(user.as_str())
+        .await
+        .context("Error getting groups")?;
+    let mut msg = String::new();
+    if groups.is_empty() {
+        msg += "This account has no groups yet. Create one using `!group new [name]`";
+    } else {
+        msg += "#### Groups\n\n";
+        for group in groups {
+            let members = queries::get_group_members(user.as_str(), &group)
+                .await
+                .context(format!("Error getting members of group {group}"))?;
+            msg += &format!("- {group} ({} members)\n", members.len());
+        }
+    }
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn show_group(room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
+    let group = queries::get_group(user.as_str(), name).await?;
+    let members = queries::get_group_members(user.as_str(), name).await?;
+    let mut msg = format!("## {}\n\n", group.name);
+    if let Some(description) = &group.description {
+        msg += &format!("{description}\n\n");
+    }
+    msg += &format!(
+        "---\n\nMembers: {}\n\nActivators: `{}`",
+        if members.is_empty() {
+            "`none`".to_owned()
+        } else {
+            members.join(", ")
+        },
+        group.activators.join(", "),
+    );
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await?;
+    Ok(())
+}
+
+async fn add_members(mut cmd: Cmd, room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
+    let mut members = vec![];
+    while let Some(member) = cmd.pop_quoted_string() {
+        if !queries::member_exists(user.as_str(), &member).await? {
+            bail!("Member {member} does not exist.\n\nCreate this member with `!m new {member}`");
+        }
+        members.push(member);
+    }
+    if members.is_empty() {
+        bail!("Please give the members to add, e.g. `!group {name} add [member...]`");
+    }
+    let mut added = vec![];
+    for member in members {
+        if queries::add_group_member(user.as_str(), name, &member)
+            .await
+            .context("Error adding group member")?
+        {
+            added.push(member);
+        }
+    }
+    let msg = if added.is_empty() {
+        format!("Those members are already in {name}")
+    } else {
+        format!("Added **{}** to {name}", added.join("**, **"))
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn remove_members(
+    mut cmd: Cmd,
+    room: &Joined,
+    user: &UserId,
+    name: &str,
+) -> anyhow::Result<()> {
+    let mut removed = vec![];
+    let mut missing = vec![];
+    while let Some(member) = cmd.pop_quoted_string() {
+        if queries::remove_group_member(user.as_str(), name, &member)
+            .await
+            .context("Error removing group member")?
+        {
+            removed.push(member);
+        } else {
+            missing.push(member);
+        }
+    }
+    if removed.is_empty() && missing.is_empty() {
+        bail!("Please give the members to remove, e.g. `!group {name} remove [member...]`");
+    }
+    let mut msg = String::new();
+    if !removed.is_empty() {
+        msg += &format!("Removed **{}** from {name}", removed.join("**, **"));
+    }
+    if !missing.is_empty() {
+        msg += &format!("\n\nNot in {name}: {}", missing.join(", "));
+    }
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn rename_group(
+    mut cmd: Cmd,
+    room: &Joined,
+    user: &UserId,
+    old_name: &str,
+) -> anyhow::Result<()> {
+    let new_name = cmd
+        .pop_word()
+        .ok_or_else(|| anyhow!("Please specify a new name"))?;
+    if RESERVED_NAMES.contains(&new_name.as_str()) {
+        bail!("`{new_name}` can't be used as a group name");
+    }
+    queries::rename_group(user.as_str(), old_name, &new_name).await?;
+    room.send(
+        RoomMessageEventContent::text_markdown(format!(
+            "Renamed group **{old_name}** to **{new_name}**"
+        )),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn set_description(cmd: Cmd, room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
+    let description = cmd.into_string();
+    if description.is_empty() {
+        bail!("Please give a description, or `!clear` to clear it");
+    }
+    let description = (description != "!clear").then_some(description);
+    queries::set_group_description(user.as_str(), name, description.as_deref()).await?;
+    let msg = match description {
+        Some(_) => format!("Updated description for {name}"),
+        None => format!("Cleared description for {name}"),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn activator_cmd(
+    mut cmd: Cmd,
+    room: &Joined,
+    user: &UserId,
+    name: &str,
+) -> anyhow::Result<()> {
+    let sub_command = cmd
+        .pop_word()
+        .ok_or_else(|| anyhow!("Please specify a sub-command"))?;
+    let activator = cmd
+        .pop_word()
+        .ok_or_else(|| anyhow!("Please give the activation sequence"))?
+        .to_lowercase();
+    let msg = match sub_command.as_str() {
+        "add" => {
+            if activator.starts_with('!') {
+                bail!("activation sequence cannot start with `!`");
+            }
+            if queries::get_members_from_activators(user.as_str(), &[activator.clone()])
+                .await?
+                .is_some()
+            {
+                bail!("`{activator}` is already in use");
+            }
+            queries::add_group_activator(user.as_str(), name, &activator)
+                .await
+                .context("Error adding activator")?;
+            format!("Added activator `{activator}` to group {name}")
+        }
+        "remove" | "rm" => {
+            queries::remove_group_activator(user.as_str(), name, &activator)
+                .await
+                .context("Error removing activator")?;
+            format!("Removed activator `{activator}` from group {name}")
+        }
+        s => bail!("Unkown command {s}"),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn remove_group(room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
+    queries::remove_group(user.as_str(), name).await?;
+    room.send(
+        RoomMessageEventContent::text_markdown(format!(
+            "Removed group `{name}`, its members were kept"
+        )),
+        None,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use anyhow::Context;
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+
+use crate::bot::parser::Cmd;
+use crate::db::queries;
+
+use super::ErrList;
+
+const DEFAULT_SWITCHES: i64 = 10;
+const MAX_SWITCHES: i64 = 100;
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let limit = cmd
+        .pop_number::<i64>()
+        .unwrap_or(DEFAULT_SWITCHES)
+        .clamp(1, MAX_SWITCHES);
+    let switches = queries::get_switches(event.sender.as_str(), limit)
+        .await
+        .context("Error getting switch history")?;
+    let msg = if switches.is_empty() {
+        "#### No switches recorded yet".to_owned()
+    } else {
+        let mut msg = "#### Switch History\n\n".to_owned();
+        for switch in switches {
+            let members = if switch.members.is_empty() {
+                "*switched out*".to_owned()
+            } else {
+                format!("**{}**", switch.members.join("**, **"))
+            };
+            msg += &format!(
+                "- {} {members} for {}\n",
+                switch.started_at,
+                format_duration(switch.duration)
+            );
+        }
+        msg
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
+
+/// Formats a number of seconds as the two largest units, e.g. `3d 4h` or `12m`.
+pub fn format_duration(secs: i64) -> String {
+    let units = [
+        (secs / 86400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+    ];
+    let parts: Vec<String> = units
+        .iter()
+        .skip_while(|(value, _)| *value == 0)
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect();
+    if parts.is_empty() {
+        "<1m".to_owned()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_duration;
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(0), "<1m");
+        assert_eq!(format_duration(59), "<1m");
+        assert_eq!(format_duration(60 * 12), "12m");
+        assert_eq!(format_duration(3600 + 60 * 5), "1h 5m");
+        assert_eq!(format_duration(86400 * 3 + 3600 * 4 + 60), "3d 4h");
+        assert_eq!(format_duration(86400 + 60), "1d");
+    }
+}
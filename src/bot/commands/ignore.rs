@@ -7,6 +7,7 @@ use matrix_sdk::Client;
 
 use crate::bot::parser::Cmd;
 use crate::db::queries;
+use crate::homeserver::HOMESERVER;
 
 use super::ErrList;
 
@@ -34,7 +35,8 @@ async fn list_ignored(user_id: &UserId, room: &Joined) -> anyhow::Result<()> {
     } else {
         let mut msg = "#### Ignored Rooms\n".to_owned();
         for room_id in ignored_rooms {
-            let room_name = queries::room_alias(&room_id)
+            let room_name = HOMESERVER
+                .room_alias(&room_id)
                 .await
                 .unwrap_or_else(|_| room_id.to_owned());
             msg += &format!("- {room_name}\n");
@@ -47,7 +49,8 @@ async fn list_ignored(user_id: &UserId, room: &Joined) -> anyhow::Result<()> {
 
 async fn toggle_ignored(room_id: &RoomId, user_id: &UserId, room: &Joined) -> anyhow::Result<()> {
     let currently_ignored = queries::is_room_ignored(user_id.as_str(), room_id.as_str()).await?;
-    let room_name = queries::room_alias(room_id.as_str())
+    let room_name = HOMESERVER
+        .room_alias(room_id.as_str())
         .await
         .unwrap_or_else(|e| {
             tracing::debug!("Error looking up alias {e:#}");
@@ -0,0 +1,721 @@
+//! Imports systems from other tools' export files.
+//!
+//! Each format is read into an [`Import`], which is then saved the same way no matter where it
+//! came from. Sending an export file into the DM shows what importing it would do, replying to
+//! the file with `!import` actually imports it.
+
+mod plural_kitty;
+mod pluralkit;
+mod simply_plural;
+mod tupperbox;
+
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    FileMessageEventContent, MessageType, OriginalSyncRoomMessageEvent, Relation,
+    RoomMessageEventContent,
+};
+use matrix_sdk::ruma::events::{
+    AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent, OriginalMessageLikeEvent,
+};
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::Client;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+
+use crate::bot::parser::Cmd;
+use crate::db::models::{AutoproxyMode, CofrontSettings, FailPolicy, Group, Member, SystemProfile};
+use crate::db::queries;
+
+use super::member::{save_member, OnConflict, Saved};
+use super::switch::SWITCH_OUT;
+use super::ErrList;
+
+/// Avatars bigger than this aren't copied over.
+const MAX_AVATAR_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+
+/// Avatar URLs come from files users send, so they can only reach public addresses. Names are
+/// checked once they are resolved and IP addresses on every redirect. Proxies are turned off
+/// because they would resolve names without the check.
+static HTTP: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("Too many redirects");
+            }
+            match check_url(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        }))
+        .no_proxy()
+        .build()
+        .expect("Error building HTTP client")
+});
+
+/// When an imported switch started.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SwitchStart {
+    /// RFC 3339
+    Timestamp(String),
+    /// Milliseconds since the Unix epoch
+    Millis(i64),
+}
+
+/// A system read from an export file, before it is saved.
+pub struct Import {
+    pub mxid: String,
+    pub system: SystemProfile,
+    /// Avatars may be `http(s)` URLs, they are copied to the homeserver when saving
+    pub members: Vec<Member>,
+    pub groups: Vec<ImportedGroup>,
+    /// Fields in the file Plural Kitty has nowhere to put, e.g. `member.banner`
+    pub unmapped: BTreeSet<String>,
+    /// Switch history as `(members, start time)`
+    pub switches: Vec<(Vec<String>, SwitchStart)>,
+    pub ignored_rooms: Vec<String>,
+    /// `(room ID, member)`
+    pub room_fronters: Vec<(String, String)>,
+    pub autoproxy: Option<AutoproxyMode>,
+    pub cofront: Option<CofrontSettings>,
+    pub fail_policy: Option<FailPolicy>,
+    pub latched_member: Option<String>,
+    /// Who is fronting, in order
+    pub fronters: Vec<String>,
+    /// Changes made while reading the file, e.g. renamed members
+    pub notes: Vec<String>,
+}
+
+pub struct ImportedGroup {
+    pub group: Group,
+    /// Member names, as they are after being added to the [`Import`]
+    pub members: Vec<String>,
+}
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let mut dry_run = false;
+    let mut on_conflict = OnConflict::Skip;
+    while let Some(word) = cmd.pop_word() {
+        match word.as_str() {
+            "dry" | "dryrun" => dry_run = true,
+            "overwrite" => on_conflict = OnConflict::Overwrite,
+            s => bail!("Unkown option `{s}`, must be `dry` or `overwrite`"),
+        }
+    }
+    let file = replied_file(room, event)
+        .await?
+        .ok_or_else(|| anyhow!("`!import` must be sent in reply to an export file"))?;
+    let import = read_file(room, &event.sender, file).await?;
+    let report = import.save(&room.client(), on_conflict, dry_run).await?;
+    room.send(RoomMessageEventContent::text_markdown(report), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
+
+/// Handles an export file sent straight into the DM by showing what importing it would do.
+pub async fn exec_file(
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+    file: &FileMessageEventContent,
+) -> anyhow::Result<ErrList> {
+    let import = read_file(room, &event.sender, file.clone()).await?;
+    let mut report = import.save(&room.client(), OnConflict::Skip, true).await?;
+    report += "\n\nReply to the file with `!import` to import it, \
+        or `!import overwrite` to replace members that already exist";
+    room.send(RoomMessageEventContent::text_markdown(report), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
+
+async fn read_file(
+    room: &Joined,
+    user: &UserId,
+    file: FileMessageEventContent,
+) -> anyhow::Result<Import> {
+    let data = room
+        .client()
+        .media()
+        .get_file(file, false)
+        .await
+        .context("Error downloading export file")?
+        .ok_or_else(|| anyhow!("The message has no file"))?;
+    parse(user.as_str(), &data)
+}
+
+/// Reads an export file, working out which tool made it from its contents.
+pub fn parse(mxid: &str, data: &[u8]) -> anyhow::Result<Import> {
+    let value: Value = serde_json::from_slice(data).context("The file isn't valid JSON")?;
+    // Plural Kitty's own exports look like PluralKit's, so they have to be checked first
+    if plural_kitty::is_export(&value) {
+        plural_kitty::parse(mxid, value).context("Error reading Plural Kitty export")
+    } else if pluralkit::is_export(&value) {
+        pluralkit::parse(mxid, value).context("Error reading PluralKit export")
+    } else if tupperbox::is_export(&value) {
+        tupperbox::parse(mxid, value).context("Error reading Tupperbox export")
+    } else if simply_plural::is_export(&value) {
+        simply_plural::parse(mxid, value).context("Error reading Simply Plural export")
+    } else {
+        bail!("Couldn't tell what made this file, only Plural Kitty, PluralKit, Tupperbox and Simply Plural exports can be imported");
+    }
+}
+
+/// Gets the file `event` is replying to, or `None` if it isn't a reply.
+async fn replied_file(
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<Option<FileMessageEventContent>> {
+    let Some(Relation::Reply { in_reply_to }) = &event.content.relates_to else {
+        return Ok(None);
+    };
+    let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(OriginalMessageLikeEvent {
+            content:
+                RoomMessageEventContent {
+                    msgtype: MessageType::File(file),
+                    ..
+                },
+            ..
+        }),
+    )) = room
+        .event(&in_reply_to.event_id)
+        .await
+        .context("Error getting file event")?
+        .event
+        .deserialize()
+        .context("Error deserializing file event")?
+    else {
+        bail!("`!import` must be sent in reply to a file");
+    };
+    Ok(Some(file))
+}
+
+impl Import {
+    pub fn new(mxid: &str) -> Self {
+        Import {
+            mxid: mxid.to_owned(),
+            system: SystemProfile::default(),
+            members: vec![],
+            groups: vec![],
+            unmapped: BTreeSet::new(),
+            switches: vec![],
+            ignored_rooms: vec![],
+            room_fronters: vec![],
+            autoproxy: None,
+            cofront: None,
+            fail_policy: None,
+            latched_member: None,
+            fronters: vec![],
+            notes: vec![],
+        }
+    }
+
+    /// Adds a member, making its name usable in commands and unique within the import. Returns
+    /// the name it ended up with.
+    pub fn add_member(&mut self, mut member: Member) -> String {
+        let original = member.name.clone();
+        let base = original.split_whitespace().collect::<Vec<_>>().join("-");
+        let base = if base.is_empty() {
+            "unnamed".to_owned()
+        } else {
+            base
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while name == SWITCH_OUT || self.members.iter().any(|m| m.name == name) {
+            n += 1;
+            name = format!("{base}-{n}");
+        }
+        if name != original {
+            self.notes
+                .push(format!("`{original}` was renamed to `{name}`"));
+            if member.display_name.is_none() && !original.trim().is_empty() {
+                member.display_name = Some(original);
+            }
+        }
+        member.name = name.clone();
+        self.members.push(member);
+        name
+    }
+
+    /// Adds a group, making its name usable in commands.
+    pub fn add_group(&mut self, mut group: Group, members: Vec<String>) {
+        let name = group.name.split_whitespace().collect::<Vec<_>>().join("-");
+        if name != group.name {
+            self.notes
+                .push(format!("Group `{}` was renamed to `{name}`", group.name));
+            group.name = name;
+        }
+        self.groups.push(ImportedGroup { group, members });
+    }
+
+    /// Records the fields in `other` that weren't mapped to anything, prefixed with `scope`.
+    /// Fields that are empty, or listed in `ignored` because they are only bookkeeping, are left
+    /// out.
+    pub fn unmapped(&mut self, scope: &str, other: &Map<String, Value>, ignored: &[&str]) {
+        for (key, value) in other {
+            let empty = match value {
+                Value::Null | Value::Bool(false) => true,
+                Value::String(s) => s.is_empty(),
+                Value::Array(a) => a.is_empty(),
+                Value::Object(o) => o.is_empty(),
+                Value::Bool(true) | Value::Number(_) => false,
+            };
+            if !empty && !ignored.contains(&key.as_str()) {
+                self.unmapped.insert(format!("{scope}{key}"));
+            }
+        }
+    }
+
+    /// Saves the import and returns a report of what happened. On a `dry_run` nothing is
+    /// written and the report says what would have happened instead.
+    pub async fn save(
+        self,
+        client: &Client,
+        on_conflict: OnConflict,
+        dry_run: bool,
+    ) -> anyhow::Result<String> {
+        let mut created = vec![];
+        let mut overwritten = vec![];
+        let mut skipped = vec![];
+        let mut warnings = vec![];
+        let new_user = !queries::user_exists(&self.mxid).await?;
+        for mut member in self.members {
+            match save_member(&member, on_conflict, true).await? {
+                Saved::Created => created.push(member.name.clone()),
+                Saved::Overwritten => overwritten.push(member.name.clone()),
+                Saved::Skipped => {
+                    skipped.push(member.name);
+                    continue;
+                }
+            }
+            if dry_run {
+                continue;
+            }
+            if let Some(url) = member.avatar.take() {
+                match reupload(client, &url).await {
+                    Ok(mxc) => member.avatar = Some(mxc),
+                    Err(e) => warnings.push(format!(
+                        "Couldn't copy the avatar of {}: {e:#}",
+                        member.name
+                    )),
+                }
+            }
+            save_member(&member, on_conflict, false).await?;
+        }
+
+        let system = &self.system;
+        let has_system = [
+            &system.name,
+            &system.tag,
+            &system.description,
+            &system.avatar,
+            &system.pronouns,
+            &system.template,
+        ]
+        .iter()
+        .any(|field| field.is_some());
+        if has_system && !dry_run {
+            save_system(client, &self.mxid, self.system, on_conflict, &mut warnings).await?;
+        }
+
+        let mut groups = vec![];
+        for ImportedGroup { group, members } in self.groups {
+            let exists = queries::group_exists(&group.mxid, &group.name).await?;
+            if exists && on_conflict == OnConflict::Skip {
+                skipped.push(format!("group {}", group.name));
+                continue;
+            }
+            groups.push(group.name.clone());
+            if dry_run {
+                continue;
+            }
+            if !exists {
+                queries::create_group(&group.mxid, &group.name).await?;
+            }
+            if group.description.is_some() {
+                queries::set_group_description(
+                    &group.mxid,
+                    &group.name,
+                    group.description.as_deref(),
+                )
+                .await?;
+            }
+            let current = queries::get_group(&group.mxid, &group.name).await?;
+            for activator in group.activators {
+                if !current.activators.contains(&activator) {
+                    queries::add_group_activator(&group.mxid, &group.name, &activator).await?;
+                }
+            }
+            for member in members {
+                if queries::member_exists(&group.mxid, &member).await? {
+                    queries::add_group_member(&group.mxid, &group.name, &member).await?;
+                }
+            }
+        }
+
+        // Settings always have a value, so only replace them when asked to
+        let has_settings = self.autoproxy.is_some()
+            || self.cofront.is_some()
+            || self.fail_policy.is_some()
+            || self.latched_member.is_some()
+            || !self.fronters.is_empty();
+        let save_settings = has_settings && (new_user || on_conflict == OnConflict::Overwrite);
+        if save_settings && !dry_run {
+            queries::create_user(&self.mxid).await?;
+            if let Some(mode) = self.autoproxy {
+                queries::set_autoproxy(&self.mxid, mode).await?;
+            }
+            if let Some(cofront) = self.cofront {
+                queries::set_cofront_separator(&self.mxid, &cofront.separator).await?;
+                queries::set_cofront_avatar(&self.mxid, cofront.avatar).await?;
+            }
+            if let Some(policy) = self.fail_policy {
+                queries::set_fail_policy(&self.mxid, Some(policy)).await?;
+            }
+        }
+
+        let mut switches = self.switches.len();
+        let mut ignored_rooms = self.ignored_rooms.len();
+        let mut room_fronters = self.room_fronters.len();
+        if !dry_run {
+            switches = 0;
+            for (members, started_at) in &self.switches {
+                let (timestamp, millis) = match started_at {
+                    SwitchStart::Timestamp(timestamp) => (Some(timestamp.as_str()), None),
+                    SwitchStart::Millis(millis) => (None, Some(*millis)),
+                };
+                if queries::add_switch(&self.mxid, members, timestamp, millis).await? {
+                    switches += 1;
+                }
+            }
+            ignored_rooms = 0;
+            for room_id in &self.ignored_rooms {
+                if !queries::is_room_ignored(&self.mxid, room_id).await? {
+                    queries::ignore_room(&self.mxid, room_id).await?;
+                    ignored_rooms += 1;
+                }
+            }
+            // After the switch history, so setting the fronters doesn't record a switch before it
+            if save_settings {
+                let mut fronters = vec![];
+                for member in self.fronters {
+                    if queries::member_exists(&self.mxid, &member).await? {
+                        fronters.push(member);
+                    }
+                }
+                if !fronters.is_empty() {
+                    queries::set_fronters(&self.mxid, &fronters).await?;
+                }
+                if let Some(member) = &self.latched_member {
+                    if queries::member_exists(&self.mxid, member).await? {
+                        queries::set_latched_member(&self.mxid, member).await?;
+                    }
+                }
+            }
+            room_fronters = 0;
+            for (room_id, member) in &self.room_fronters {
+                let current = queries::get_room_fronter(&self.mxid, room_id).await?;
+                if (current.is_none() || on_conflict == OnConflict::Overwrite)
+                    && queries::member_exists(&self.mxid, member).await?
+                {
+                    queries::set_room_fronter(&self.mxid, room_id, member).await?;
+                    room_fronters += 1;
+                }
+            }
+        }
+
+        let mut report = String::new();
+        let (verb, overwrite_verb) = if dry_run {
+            report += "**Dry run, nothing was changed**\n\n";
+            ("Would import", "Would update")
+        } else {
+            ("Imported", "Updated")
+        };
+        report += &match created.len() {
+            0 => "No new members".to_owned(),
+            n => format!("{verb} {n} members: {}", created.join(", ")),
+        };
+        if !overwritten.is_empty() {
+            report += &format!("\n\n{overwrite_verb} {}", overwritten.join(", "));
+        }
+        if has_system {
+            report += &format!("\n\n{verb} the system profile");
+        }
+        if !groups.is_empty() {
+            report += &format!("\n\n{verb} groups: {}", groups.join(", "));
+        }
+        if switches > 0 {
+            report += &format!("\n\n{verb} {switches} switches");
+        }
+        if ignored_rooms > 0 {
+            report += &format!("\n\n{verb} {ignored_rooms} ignored rooms");
+        }
+        if room_fronters > 0 {
+            report += &format!("\n\n{verb} {room_fronters} room fronters");
+        }
+        if save_settings {
+            report += &format!("\n\n{verb} your settings and current fronters");
+        } else if has_settings {
+            report += "\n\nKept your current settings and fronters, \
+                use `!import overwrite` to replace them";
+        }
+        if !skipped.is_empty() {
+            report += &format!(
+                "\n\nSkipped because the name is already in use: {}. \
+                Use `!import overwrite` to update them instead",
+                skipped.join(", ")
+            );
+        }
+        for note in self.notes {
+            report += &format!("\n\n{note}");
+        }
+        if !self.unmapped.is_empty() {
+            let fields: Vec<String> = self.unmapped.iter().map(|f| format!("`{f}`")).collect();
+            report += &format!(
+                "\n\nThese fields couldn't be imported: {}",
+                fields.join(", ")
+            );
+        }
+        for warning in warnings {
+            report += &format!("\n\n{warning}");
+        }
+        Ok(report)
+    }
+}
+
+/// Saves the imported system profile. Fields that are already set are only replaced when
+/// overwriting.
+async fn save_system(
+    client: &Client,
+    mxid: &str,
+    system: SystemProfile,
+    on_conflict: OnConflict,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    queries::create_user(mxid).await?;
+    let current = queries::get_system(mxid).await?;
+    let pick = |current: Option<String>, new: Option<String>| match on_conflict {
+        OnConflict::Overwrite => new.or(current),
+        OnConflict::Skip => current.or(new),
+    };
+    let avatar = match (&current.avatar, system.avatar) {
+        (Some(_), _) if on_conflict == OnConflict::Skip => current.avatar.clone(),
+        (_, Some(url)) => match reupload(client, &url).await {
+            Ok(mxc) => Some(mxc),
+            Err(e) => {
+                warnings.push(format!("Couldn't copy the system avatar: {e:#}"));
+                current.avatar.clone()
+            }
+        },
+        (_, None) => current.avatar.clone(),
+    };
+    queries::set_system_name(mxid, pick(current.name, system.name).as_deref()).await?;
+    queries::set_system_tag(mxid, pick(current.tag, system.tag).as_deref()).await?;
+    queries::set_system_description(
+        mxid,
+        pick(current.description, system.description).as_deref(),
+    )
+    .await?;
+    queries::set_system_pronouns(mxid, pick(current.pronouns, system.pronouns).as_deref()).await?;
+    queries::set_display_name_template(mxid, pick(current.template, system.template).as_deref())
+        .await?;
+    queries::set_system_avatar(mxid, avatar.as_deref()).await?;
+    Ok(())
+}
+
+/// Copies an avatar from a URL into the homeserver's media repository and returns its mxc URL.
+/// Avatars that are already mxc URLs are kept as they are.
+pub async fn reupload(client: &Client, url: &str) -> anyhow::Result<String> {
+    if url.starts_with("mxc://") {
+        return Ok(url.to_owned());
+    }
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("{url} isn't a valid URL"))?;
+    check_url(&parsed)?;
+    let mut response = HTTP
+        .get(parsed)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Error downloading {url}"))?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_AVATAR_SIZE)
+    {
+        bail!("{url} is too big");
+    }
+    let content_type: mime::Mime = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(mime::IMAGE_PNG);
+    if content_type.type_() != mime::IMAGE {
+        bail!("{url} is not an image");
+    }
+    // Servers don't have to send a length, so stop reading once the avatar gets too big
+    let mut data = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_context(|| format!("Error downloading {url}"))?
+    {
+        if (data.len() + chunk.len()) as u64 > MAX_AVATAR_SIZE {
+            bail!("{url} is too big");
+        }
+        data.extend_from_slice(&chunk);
+    }
+    let response = client
+        .media()
+        .upload(&content_type, data)
+        .await
+        .context("Error uploading avatar")?;
+    Ok(response.content_uri.to_string())
+}
+
+/// Only lets avatars be fetched over HTTP(S). Hosts given as IP addresses have to be public,
+/// names are checked by [`PublicResolver`] once they are resolved.
+fn check_url(url: &reqwest::Url) -> anyhow::Result<()> {
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("{url} isn't an http(s) URL");
+    }
+    let host = url.host_str().ok_or_else(|| anyhow!("{url} has no host"))?;
+    let ip = host.trim_start_matches('[').trim_end_matches(']').parse();
+    if ip.is_ok_and(|ip| !is_public(ip)) {
+        bail!("{url} isn't a public address");
+    }
+    Ok(())
+}
+
+/// Resolves host names, leaving out addresses that aren't public.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(resolve_public(name.as_str().to_owned()))
+    }
+}
+
+async fn resolve_public(
+    host: String,
+) -> Result<reqwest::dns::Addrs, Box<dyn std::error::Error + Send + Sync>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+        .await?
+        .filter(|addr| is_public(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{host} doesn't resolve to a public address").into());
+    }
+    Ok(Box::new(addrs.into_iter()))
+}
+
+/// Whether an address is on the public internet, rather than e.g. loopback, private, link-local
+/// or reserved.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space for carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                // Benchmarking
+                || (a == 198 && (b == 18 || b == 19))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let s = ip.segments();
+            // NAT64 addresses reach the IPv4 address in their last 32 bits
+            if s[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [a, b] = s[6].to_be_bytes();
+                let [c, d] = s[7].to_be_bytes();
+                return is_public(IpAddr::V4([a, b, c, d].into()));
+            }
+            !(s[..6] == [0; 6]
+                || ip.is_multicast()
+                // Unique local
+                || (s[0] & 0xfe00) == 0xfc00
+                // Link-local and the old site-local range
+                || (s[0] & 0xffc0) == 0xfe80
+                || (s[0] & 0xffc0) == 0xfec0
+                // Documentation
+                || (s[0] == 0x2001 && s[1] == 0x0db8))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_url, is_public, Import};
+    use crate::db::models::Member;
+
+    #[test]
+    fn public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+            "64:ff9b::808:808",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn urls() {
+        let check = |url: &str| check_url(&url.parse().unwrap());
+        assert!(check("https://example.com/a.png").is_ok());
+        assert!(check("https://1.1.1.1/a.png").is_ok());
+        assert!(check("http://127.0.0.1:8008/_synapse/admin").is_err());
+        assert!(check("http://[::1]/a.png").is_err());
+        assert!(check("file:///etc/passwd").is_err());
+    }
+
+    #[test]
+    fn reserved_member_names() {
+        let mut import = Import::new("@test:test.local");
+        let name = import.add_member(Member::new("@test:test.local", "out"));
+        assert_eq!(name, "out-2");
+        assert_eq!(import.members[0].display_name.as_deref(), Some("out"));
+    }
+}
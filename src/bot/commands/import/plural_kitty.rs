@@ -0,0 +1,289 @@
+//! Reads the JSON file made by `!export`.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use serde_json::Value;
+
+use crate::bot::commands::export::{ExportFile, EXPORT_VERSION};
+use crate::bot::commands::member::{parse_birthday, parse_color};
+use crate::db::models::{CofrontSettings, Group, Member};
+
+use super::{Import, SwitchStart};
+
+pub fn is_export(value: &Value) -> bool {
+    value.get("plural_kitty").is_some_and(Value::is_object)
+}
+
+pub fn parse(mxid: &str, value: Value) -> anyhow::Result<Import> {
+    let export: ExportFile = serde_json::from_value(value)?;
+    let data = export.plural_kitty;
+    if data.version > EXPORT_VERSION {
+        bail!(
+            "This file was made by a newer version of Plural Kitty (export version {})",
+            data.version
+        );
+    }
+    let mut import = Import::new(mxid);
+    import.system.name = export.name;
+    import.system.description = export.description;
+    import.system.tag = export.tag;
+    import.system.pronouns = export.pronouns;
+    import.system.avatar = export.avatar_url;
+    import.system.template = data.display_name_template;
+    import.autoproxy = Some(data.autoproxy.parse()?);
+    import.cofront = Some(CofrontSettings {
+        separator: data.cofront_separator,
+        avatar: data.cofront_avatar.parse()?,
+    });
+    import.ignored_rooms = data.ignored_rooms;
+    import.fail_policy = data.fail_policy.map(|policy| policy.parse()).transpose()?;
+
+    let mut names = HashMap::new();
+    for exported in export.members {
+        let mut member = Member::new(mxid, &exported.name);
+        member.display_name = exported.display_name;
+        member.avatar = exported.avatar_url;
+        member.activators = exported.activators;
+        member.track_account = exported.track_account;
+        member.proxy_tags = exported
+            .proxy_tags
+            .into_iter()
+            .map(|tag| {
+                format!(
+                    "{}text{}",
+                    tag.prefix.unwrap_or_default(),
+                    tag.suffix.unwrap_or_default()
+                )
+            })
+            .collect();
+        member.pronouns = exported.pronouns;
+        member.description = exported.description;
+        member.color = exported.color.as_deref().and_then(parse_color);
+        member.birthday = exported.birthday.as_deref().and_then(parse_birthday);
+        member.notes = exported.notes;
+        exported.privacy.apply(&mut member);
+        let name = import.add_member(member);
+        names.insert(exported.id, name);
+    }
+    // Members are referred to by their name at export time, which may have changed on import
+    let rename = |members: &[String]| -> Vec<String> {
+        members
+            .iter()
+            .filter_map(|id| names.get(id).cloned())
+            .collect()
+    };
+
+    for exported in export.groups {
+        import.add_group(
+            Group {
+                mxid: mxid.to_owned(),
+                name: exported.name,
+                description: exported.description,
+                activators: exported.activators,
+            },
+            rename(&exported.members),
+        );
+    }
+    import.switches = export
+        .switches
+        .into_iter()
+        .map(|switch| {
+            (
+                rename(&switch.members),
+                SwitchStart::Timestamp(switch.timestamp),
+            )
+        })
+        .collect();
+    import.room_fronters = data
+        .room_fronters
+        .into_iter()
+        .filter_map(|fronter| Some((fronter.room_id, names.get(&fronter.member)?.clone())))
+        .collect();
+    import.latched_member = data
+        .latched_member
+        .and_then(|member| names.get(&member).cloned());
+    import.fronters = rename(&data.fronters);
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{is_export, parse};
+    use crate::bot::commands::export::{
+        ExportFile, ExportGroup, ExportMember, ExportPrivacy, ExportProxyTag, ExportRoomFronter,
+        ExportSwitch, PluralKittyData, EXPORT_VERSION,
+    };
+    use crate::db::models::{AutoproxyMode, CofrontAvatar, FailPolicy, Member};
+
+    #[test]
+    fn round_trip() {
+        let export = ExportFile {
+            version: 2,
+            name: Some("The Kitties".to_owned()),
+            description: None,
+            tag: Some("🐱".to_owned()),
+            pronouns: None,
+            avatar_url: Some("mxc://test.local/system".to_owned()),
+            members: vec![ExportMember {
+                id: "sasha".to_owned(),
+                name: "sasha".to_owned(),
+                display_name: Some("Sashanoraa".to_owned()),
+                pronouns: Some("ze/zir".to_owned()),
+                description: Some("Likes cats".to_owned()),
+                color: Some("ff8800".to_owned()),
+                birthday: Some("0004-03-04".to_owned()),
+                avatar_url: Some("mxc://test.local/sasha".to_owned()),
+                proxy_tags: vec![ExportProxyTag {
+                    prefix: Some("s:".to_owned()),
+                    suffix: None,
+                }],
+                activators: vec!["s".to_owned()],
+                track_account: true,
+                notes: Some("notes".to_owned()),
+                privacy: ExportPrivacy {
+                    visibility: Some("public".to_owned()),
+                    pronoun_privacy: Some("private".to_owned()),
+                    ..Default::default()
+                },
+            }],
+            groups: vec![ExportGroup {
+                id: "cats".to_owned(),
+                name: "cats".to_owned(),
+                description: None,
+                members: vec!["sasha".to_owned()],
+                activators: vec!["c".to_owned()],
+            }],
+            switches: vec![ExportSwitch {
+                timestamp: "2026-10-18T12:00:00.000000Z".to_owned(),
+                members: vec!["sasha".to_owned()],
+            }],
+            plural_kitty: PluralKittyData {
+                version: EXPORT_VERSION,
+                display_name_template: Some("{name} | {tag}".to_owned()),
+                autoproxy: "latch".to_owned(),
+                cofront_separator: ",".to_owned(),
+                cofront_avatar: "account".to_owned(),
+                ignored_rooms: vec!["!room:test.local".to_owned()],
+                room_fronters: vec![],
+                fail_policy: Some("retry".to_owned()),
+                latched_member: Some("sasha".to_owned()),
+                fronters: vec!["sasha".to_owned()],
+            },
+        };
+        let value = serde_json::to_value(export).unwrap();
+        assert!(is_export(&value));
+        let import = parse("@test:test.local", value).unwrap();
+        let sasha = &import.members[0];
+        assert_eq!(sasha.name, "sasha");
+        assert_eq!(sasha.display_name.as_deref(), Some("Sashanoraa"));
+        assert_eq!(sasha.avatar.as_deref(), Some("mxc://test.local/sasha"));
+        assert_eq!(sasha.proxy_tags, ["s:text"]);
+        assert_eq!(sasha.activators, ["s"]);
+        assert!(sasha.track_account);
+        assert_eq!(sasha.birthday.as_deref(), Some("03-04"));
+        assert_eq!(sasha.notes.as_deref(), Some("notes"));
+        assert!(!sasha.private);
+        assert_eq!(sasha.private_fields, ["pronouns"]);
+        assert_eq!(import.groups[0].group.activators, ["c"]);
+        assert_eq!(import.groups[0].members, ["sasha"]);
+        assert_eq!(import.switches[0].0, ["sasha"]);
+        assert_eq!(import.system.template.as_deref(), Some("{name} | {tag}"));
+        assert_eq!(import.autoproxy, Some(AutoproxyMode::Latch));
+        assert_eq!(
+            import.cofront.map(|c| c.avatar),
+            Some(CofrontAvatar::Account)
+        );
+        assert_eq!(import.ignored_rooms, ["!room:test.local"]);
+        assert_eq!(import.fail_policy, Some(FailPolicy::Retry));
+        assert_eq!(import.latched_member.as_deref(), Some("sasha"));
+        assert_eq!(import.fronters, ["sasha"]);
+        assert!(import.notes.is_empty() && import.unmapped.is_empty());
+    }
+
+    #[test]
+    fn renamed_members_keep_groups() {
+        let group = |name: &str, members: &[&str]| ExportGroup {
+            id: name.to_owned(),
+            name: name.to_owned(),
+            description: None,
+            members: members.iter().map(|&m| m.to_owned()).collect(),
+            activators: vec![],
+        };
+        let export = ExportFile {
+            version: 2,
+            name: None,
+            description: None,
+            tag: None,
+            pronouns: None,
+            avatar_url: None,
+            members: ["sasha", "kit kat", "kit-kat"]
+                .into_iter()
+                .map(|name| ExportMember::from_member(Member::new("@test:test.local", name)))
+                .collect(),
+            groups: vec![
+                group("cats", &["sasha", "kit kat"]),
+                group("dupes", &["kit-kat"]),
+            ],
+            switches: vec![ExportSwitch {
+                timestamp: "2026-10-18T12:00:00.000000Z".to_owned(),
+                members: vec!["kit kat".to_owned(), "kit-kat".to_owned()],
+            }],
+            plural_kitty: PluralKittyData {
+                version: EXPORT_VERSION,
+                display_name_template: None,
+                autoproxy: "off".to_owned(),
+                cofront_separator: ", ".to_owned(),
+                cofront_avatar: "first".to_owned(),
+                ignored_rooms: vec![],
+                room_fronters: vec![ExportRoomFronter {
+                    room_id: "!room:test.local".to_owned(),
+                    member: "kit kat".to_owned(),
+                }],
+                fail_policy: None,
+                latched_member: Some("kit-kat".to_owned()),
+                fronters: vec!["sasha".to_owned(), "kit kat".to_owned()],
+            },
+        };
+        let value = serde_json::to_value(export).unwrap();
+        let import = parse("@test:test.local", value).unwrap();
+        let names: Vec<_> = import.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["sasha", "kit-kat", "kit-kat-2"]);
+        assert_eq!(import.groups[0].members, ["sasha", "kit-kat"]);
+        assert_eq!(import.groups[1].members, ["kit-kat-2"]);
+        assert_eq!(import.switches[0].0, ["kit-kat", "kit-kat-2"]);
+        assert_eq!(
+            import.room_fronters,
+            [("!room:test.local".to_owned(), "kit-kat".to_owned())]
+        );
+        assert_eq!(import.latched_member.as_deref(), Some("kit-kat-2"));
+        assert_eq!(import.fronters, ["sasha", "kit-kat"]);
+    }
+
+    #[test]
+    fn newer_versions_rejected() {
+        let export = |version: u32| {
+            json!({
+                "version": 2,
+                "members": [],
+                "groups": [],
+                "switches": [],
+                "plural_kitty": {
+                    "version": version,
+                    "autoproxy": "off",
+                    "cofront_separator": ", ",
+                    "cofront_avatar": "first",
+                    "ignored_rooms": [],
+                    "room_fronters": [],
+                },
+            })
+        };
+        assert!(parse("@test:test.local", export(EXPORT_VERSION)).is_ok());
+        let Err(err) = parse("@test:test.local", export(EXPORT_VERSION + 1)) else {
+            panic!("Accepted a newer export version");
+        };
+        assert!(err.to_string().contains("newer version"), "{err}");
+    }
+}
@@ -0,0 +1,220 @@
+//! Reads the JSON file made by PluralKit's `pk;export` command.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::bot::commands::export::ExportPrivacy;
+use crate::bot::commands::member::{parse_birthday, parse_color};
+use crate::db::models::{Group, Member};
+
+use super::{Import, SwitchStart};
+
+/// Fields that only matter to PluralKit itself, so aren't reported as lost.
+const IGNORED: &[&str] = &[
+    "version",
+    "id",
+    "uuid",
+    "system",
+    "created",
+    "accounts",
+    "message_count",
+    "last_message_timestamp",
+    "webhook_url",
+];
+
+#[derive(Deserialize)]
+struct Export {
+    name: Option<String>,
+    description: Option<String>,
+    tag: Option<String>,
+    pronouns: Option<String>,
+    avatar_url: Option<String>,
+    #[serde(default)]
+    members: Vec<PkMember>,
+    #[serde(default)]
+    groups: Vec<PkGroup>,
+    #[serde(default)]
+    switches: Vec<PkSwitch>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct PkMember {
+    id: String,
+    name: String,
+    display_name: Option<String>,
+    pronouns: Option<String>,
+    description: Option<String>,
+    color: Option<String>,
+    birthday: Option<String>,
+    avatar_url: Option<String>,
+    webhook_avatar_url: Option<String>,
+    #[serde(default)]
+    proxy_tags: Vec<PkProxyTag>,
+    /// Only the settings Plural Kitty has an equivalent for are read
+    privacy: Option<ExportPrivacy>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct PkProxyTag {
+    prefix: Option<String>,
+    suffix: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PkGroup {
+    name: String,
+    description: Option<String>,
+    /// Member IDs
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct PkSwitch {
+    timestamp: String,
+    /// Member IDs
+    members: Vec<String>,
+}
+
+pub fn is_export(value: &Value) -> bool {
+    value.get("members").is_some_and(Value::is_array)
+        && value.get("switches").is_some_and(Value::is_array)
+}
+
+pub fn parse(mxid: &str, value: Value) -> anyhow::Result<Import> {
+    let export: Export = serde_json::from_value(value)?;
+    let mut import = Import::new(mxid);
+    import.system.name = export.name;
+    import.system.description = export.description;
+    import.system.tag = export.tag;
+    import.system.pronouns = export.pronouns;
+    import.system.avatar = export.avatar_url;
+    import.unmapped("system.", &export.other, IGNORED);
+
+    let mut names = HashMap::new();
+    for pk_member in export.members {
+        import.unmapped("member.", &pk_member.other, IGNORED);
+        let mut member = Member::new(mxid, &pk_member.name);
+        member.display_name = pk_member.display_name;
+        member.pronouns = pk_member.pronouns;
+        member.description = pk_member.description;
+        member.avatar = pk_member.webhook_avatar_url.or(pk_member.avatar_url);
+        member.proxy_tags = pk_member
+            .proxy_tags
+            .into_iter()
+            .map(|tag| {
+                format!(
+                    "{}text{}",
+                    tag.prefix.unwrap_or_default(),
+                    tag.suffix.unwrap_or_default()
+                )
+            })
+            .filter(|tag| tag != "text")
+            .collect();
+        if let Some(color) = pk_member.color {
+            member.color = parse_color(&color);
+            if member.color.is_none() {
+                import.unmapped.insert("member.color".to_owned());
+            }
+        }
+        if let Some(birthday) = pk_member.birthday {
+            member.birthday = parse_birthday(&birthday);
+            if member.birthday.is_none() {
+                import.unmapped.insert("member.birthday".to_owned());
+            }
+        }
+        if let Some(privacy) = pk_member.privacy {
+            privacy.apply(&mut member);
+        }
+        let name = import.add_member(member);
+        names.insert(pk_member.id, name);
+    }
+
+    for pk_group in export.groups {
+        import.unmapped("group.", &pk_group.other, IGNORED);
+        let members = pk_group
+            .members
+            .iter()
+            .filter_map(|id| names.get(id).cloned())
+            .collect();
+        import.add_group(
+            Group {
+                mxid: mxid.to_owned(),
+                name: pk_group.name,
+                description: pk_group.description,
+                activators: vec![],
+            },
+            members,
+        );
+    }
+    import.switches = export
+        .switches
+        .into_iter()
+        .map(|switch| {
+            let members = switch
+                .members
+                .iter()
+                .filter_map(|id| names.get(id).cloned())
+                .collect();
+            (members, SwitchStart::Timestamp(switch.timestamp))
+        })
+        .collect();
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{is_export, parse};
+
+    #[test]
+    fn pluralkit_export() {
+        let export = json!({
+            "version": 2,
+            "id": "abcde",
+            "name": "The Kitties",
+            "tag": "🐱",
+            "banner": "https://example.com/banner.png",
+            "members": [
+                {
+                    "id": "aaaaa",
+                    "name": "Sasha Nora",
+                    "pronouns": "ze/zir",
+                    "color": "FF8800",
+                    "birthday": "0004-03-04",
+                    "proxy_tags": [{"prefix": "s:", "suffix": null}, {"prefix": null, "suffix": null}],
+                    "keep_proxy": false,
+                    "privacy": {"visibility": "private", "name_privacy": "private", "pronoun_privacy": "private"},
+                },
+                {"id": "bbbbb", "name": "ursa", "display_name": "Ursa", "proxy_tags": []},
+            ],
+            "groups": [{"id": "ccccc", "name": "cats", "members": ["aaaaa", "bbbbb", "zzzzz"]}],
+            "switches": [{"timestamp": "2023-06-12T09:03:48.123456Z", "members": ["bbbbb"]}],
+        });
+        assert!(is_export(&export));
+        let import = parse("@test:test.local", export).unwrap();
+        assert_eq!(import.system.name.as_deref(), Some("The Kitties"));
+        let sasha = &import.members[0];
+        assert_eq!(sasha.name, "Sasha-Nora");
+        assert_eq!(sasha.display_name.as_deref(), Some("Sasha Nora"));
+        assert_eq!(sasha.color.as_deref(), Some("ff8800"));
+        assert_eq!(sasha.birthday.as_deref(), Some("03-04"));
+        assert_eq!(sasha.proxy_tags, ["s:text"]);
+        assert!(sasha.private);
+        assert_eq!(sasha.private_fields, ["pronouns"]);
+        assert!(!import.members[1].private);
+        assert_eq!(import.groups[0].members, ["Sasha-Nora", "ursa"]);
+        assert_eq!(import.switches[0].0, ["ursa"]);
+        let unmapped: Vec<&str> = import.unmapped.iter().map(String::as_str).collect();
+        assert_eq!(unmapped, ["system.banner"]);
+    }
+}
@@ -0,0 +1,262 @@
+//! Reads the JSON file made by Simply Plural's "Export data" option.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::bot::commands::member::parse_color;
+use crate::db::models::{Group, Member};
+
+use super::{Import, SwitchStart};
+
+/// Fields that only matter to Simply Plural itself, so aren't reported as lost.
+const IGNORED: &[&str] = &[
+    "_id",
+    "uid",
+    "lastOperationTime",
+    "supportDescMarkdown",
+    "pkId",
+    "avatarUuid",
+    "parent",
+];
+
+/// Where Simply Plural keeps avatars uploaded to it, followed by `{uid}/{avatarUuid}`.
+const AVATAR_BASE_URL: &str = "https://spaces.apparyllis.com/avatars";
+
+#[derive(Deserialize)]
+struct Export {
+    members: Vec<SpMember>,
+    #[serde(default)]
+    groups: Vec<SpGroup>,
+    #[serde(default, rename = "frontHistory")]
+    front_history: Vec<SpFront>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct SpMember {
+    #[serde(rename = "_id")]
+    id: String,
+    uid: Option<String>,
+    name: String,
+    desc: Option<String>,
+    pronouns: Option<String>,
+    color: Option<String>,
+    #[serde(rename = "avatarUrl")]
+    avatar_url: Option<String>,
+    #[serde(rename = "avatarUuid")]
+    avatar_uuid: Option<String>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct SpGroup {
+    name: String,
+    desc: Option<String>,
+    /// Member IDs
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct SpFront {
+    member: String,
+    /// Milliseconds since the Unix epoch
+    #[serde(rename = "startTime")]
+    start_time: i64,
+    #[serde(rename = "endTime")]
+    end_time: Option<i64>,
+    /// Set when `member` is a custom front rather than a member
+    #[serde(default)]
+    custom: bool,
+}
+
+pub fn is_export(value: &Value) -> bool {
+    value.get("members").is_some_and(Value::is_array)
+        && (value.get("frontHistory").is_some() || value.get("customFronts").is_some())
+}
+
+pub fn parse(mxid: &str, value: Value) -> anyhow::Result<Import> {
+    let export: Export = serde_json::from_value(value)?;
+    let mut import = Import::new(mxid);
+    import.unmapped("", &export.other, IGNORED);
+
+    let mut names = HashMap::new();
+    for sp_member in export.members {
+        import.unmapped("member.", &sp_member.other, IGNORED);
+        let mut member = Member::new(mxid, &sp_member.name);
+        member.description = sp_member.desc.filter(|desc| !desc.is_empty());
+        member.pronouns = sp_member.pronouns.filter(|pronouns| !pronouns.is_empty());
+        member.avatar = match (sp_member.avatar_url, sp_member.avatar_uuid, sp_member.uid) {
+            (Some(url), _, _) if !url.is_empty() => Some(url),
+            (_, Some(uuid), Some(uid)) if !uuid.is_empty() => {
+                Some(format!("{AVATAR_BASE_URL}/{uid}/{uuid}"))
+            }
+            _ => None,
+        };
+        if let Some(color) = sp_member.color.filter(|color| !color.is_empty()) {
+            member.color = parse_color(&color);
+            if member.color.is_none() {
+                import.unmapped.insert("member.color".to_owned());
+            }
+        }
+        let name = import.add_member(member);
+        names.insert(sp_member.id, name);
+    }
+
+    for sp_group in export.groups {
+        import.unmapped("group.", &sp_group.other, IGNORED);
+        let members = sp_group
+            .members
+            .iter()
+            .filter_map(|id| names.get(id).cloned())
+            .collect();
+        import.add_group(
+            Group {
+                mxid: mxid.to_owned(),
+                name: sp_group.name,
+                description: sp_group.desc.filter(|desc| !desc.is_empty()),
+                activators: vec![],
+            },
+            members,
+        );
+    }
+
+    let mut fronts = vec![];
+    for front in export.front_history {
+        if front.custom {
+            import.unmapped.insert("frontHistory.custom".to_owned());
+        } else if let Some(name) = names.get(&front.member) {
+            fronts.push((name.clone(), front.start_time, front.end_time));
+        }
+    }
+    import.switches = switches_from_fronts(fronts)
+        .into_iter()
+        .map(|(members, time)| (members, SwitchStart::Millis(time)))
+        .collect();
+    Ok(import)
+}
+
+/// Simply Plural records when each member started and stopped fronting. This turns that into a
+/// switch at every point where the set of fronters changed.
+fn switches_from_fronts(fronts: Vec<(String, i64, Option<i64>)>) -> Vec<(Vec<String>, i64)> {
+    // `(time, starts, member)`, at the same time fronts end before others start
+    let mut events = vec![];
+    for (name, start, end) in fronts {
+        match end {
+            Some(end) if end <= start => continue,
+            Some(end) => events.push((end, false, name.clone())),
+            None => {}
+        }
+        events.push((start, true, name));
+    }
+    events.sort_by_key(|(time, starts, _)| (*time, *starts));
+    let mut fronting: Vec<String> = vec![];
+    let mut switches: Vec<(Vec<String>, i64)> = vec![];
+    let mut events = events.into_iter().peekable();
+    while let Some((time, starts, name)) = events.next() {
+        if starts {
+            fronting.push(name);
+        } else if let Some(i) = fronting.iter().position(|n| *n == name) {
+            fronting.remove(i);
+        }
+        if events.peek().is_some_and(|(next, _, _)| *next == time) {
+            continue;
+        }
+        let mut members: Vec<String> = vec![];
+        for name in &fronting {
+            if !members.contains(name) {
+                members.push(name.clone());
+            }
+        }
+        if switches.last().map(|(last, _)| last) != Some(&members) {
+            switches.push((members, time));
+        }
+    }
+    switches
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{is_export, parse, switches_from_fronts, SwitchStart};
+
+    #[test]
+    fn simply_plural_export() {
+        let export = json!({
+            "members": [
+                {"_id": "m1", "uid": "u", "name": "Sasha", "color": "#FF8800", "avatarUuid": "a1", "info": {"f": "x"}},
+                {"_id": "m2", "uid": "u", "name": "Ursa", "desc": "", "private": false},
+            ],
+            "groups": [{"_id": "g1", "name": "cats", "members": ["m1", "m2"], "emoji": "🐱"}],
+            "frontHistory": [
+                {"member": "m1", "startTime": 1_000, "endTime": 3_000, "custom": false},
+                {"member": "m2", "startTime": 2_000, "endTime": null, "custom": false},
+                {"member": "c1", "startTime": 2_500, "endTime": null, "custom": true},
+            ],
+            "customFronts": [{"_id": "c1", "name": "Sleeping"}],
+        });
+        assert!(is_export(&export));
+        let import = parse("@test:test.local", export).unwrap();
+        let sasha = &import.members[0];
+        assert_eq!(sasha.color.as_deref(), Some("ff8800"));
+        assert_eq!(
+            sasha.avatar.as_deref(),
+            Some("https://spaces.apparyllis.com/avatars/u/a1")
+        );
+        assert_eq!(import.members[1].description, None);
+        assert_eq!(import.groups[0].members, ["Sasha", "Ursa"]);
+        let switches: Vec<Vec<&str>> = import
+            .switches
+            .iter()
+            .map(|(members, _)| members.iter().map(String::as_str).collect())
+            .collect();
+        assert_eq!(
+            switches,
+            [vec!["Sasha"], vec!["Sasha", "Ursa"], vec!["Ursa"]]
+        );
+        assert_eq!(import.switches[0].1, SwitchStart::Millis(1_000));
+        let unmapped: Vec<&str> = import.unmapped.iter().map(String::as_str).collect();
+        assert_eq!(
+            unmapped,
+            [
+                "customFronts",
+                "frontHistory.custom",
+                "group.emoji",
+                "member.info"
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_fronts() {
+        let fronts = vec![
+            ("a".to_owned(), 0, Some(10)),
+            ("b".to_owned(), 5, Some(10)),
+            ("a".to_owned(), 10, None),
+            ("c".to_owned(), 20, Some(20)),
+            ("b".to_owned(), 30, Some(40)),
+        ];
+        let switches = switches_from_fronts(fronts);
+        let switches: Vec<(Vec<&str>, i64)> = switches
+            .iter()
+            .map(|(members, time)| (members.iter().map(String::as_str).collect(), *time))
+            .collect();
+        assert_eq!(
+            switches,
+            [
+                (vec!["a"], 0),
+                (vec!["a", "b"], 5),
+                (vec!["a"], 10),
+                (vec!["a", "b"], 30),
+                (vec!["a"], 40),
+            ]
+        );
+    }
+}
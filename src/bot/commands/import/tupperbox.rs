@@ -0,0 +1,141 @@
+//! Reads the JSON file made by Tupperbox's `tul!export` command.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::bot::commands::member::parse_birthday;
+use crate::db::models::{Group, Member};
+
+use super::Import;
+
+/// Fields that only matter to Tupperbox itself, so aren't reported as lost.
+const IGNORED: &[&str] = &[
+    "id",
+    "user_id",
+    "position",
+    "posts",
+    "show_brackets",
+    "created_at",
+    "last_used",
+    "group_pos",
+];
+
+#[derive(Deserialize)]
+struct Export {
+    tuppers: Vec<Tupper>,
+    #[serde(default)]
+    groups: Vec<TupperGroup>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Tupper {
+    name: String,
+    nick: Option<String>,
+    avatar_url: Option<String>,
+    /// Prefix and suffix pairs, one pair per set of brackets
+    #[serde(default)]
+    brackets: Vec<String>,
+    description: Option<String>,
+    /// An ISO 8601 timestamp
+    birthday: Option<String>,
+    group_id: Option<i64>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct TupperGroup {
+    id: i64,
+    name: String,
+    description: Option<String>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+pub fn is_export(value: &Value) -> bool {
+    value.get("tuppers").is_some_and(Value::is_array)
+}
+
+pub fn parse(mxid: &str, value: Value) -> anyhow::Result<Import> {
+    let export: Export = serde_json::from_value(value)?;
+    let mut import = Import::new(mxid);
+    import.unmapped("", &export.other, IGNORED);
+
+    let mut group_members: HashMap<i64, Vec<String>> = HashMap::new();
+    for tupper in export.tuppers {
+        import.unmapped("tupper.", &tupper.other, IGNORED);
+        let mut member = Member::new(mxid, &tupper.name);
+        member.display_name = tupper.nick;
+        member.avatar = tupper.avatar_url;
+        member.description = tupper.description;
+        member.proxy_tags = tupper
+            .brackets
+            .chunks(2)
+            .map(|pair| format!("{}text{}", pair[0], pair.get(1).map_or("", String::as_str)))
+            .filter(|tag| tag != "text")
+            .collect();
+        if let Some(birthday) = tupper.birthday {
+            member.birthday = birthday.get(..10).and_then(parse_birthday);
+            if member.birthday.is_none() {
+                import.unmapped.insert("tupper.birthday".to_owned());
+            }
+        }
+        let name = import.add_member(member);
+        if let Some(group_id) = tupper.group_id {
+            group_members.entry(group_id).or_default().push(name);
+        }
+    }
+
+    for tupper_group in export.groups {
+        import.unmapped("group.", &tupper_group.other, IGNORED);
+        import.add_group(
+            Group {
+                mxid: mxid.to_owned(),
+                name: tupper_group.name,
+                description: tupper_group.description,
+                activators: vec![],
+            },
+            group_members.remove(&tupper_group.id).unwrap_or_default(),
+        );
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{is_export, parse};
+
+    #[test]
+    fn tupperbox_export() {
+        let export = json!({
+            "tuppers": [{
+                "id": 1,
+                "user_id": "1234",
+                "name": "Sasha",
+                "nick": "Sashanoraa",
+                "brackets": ["s:", "", "[", "]"],
+                "birthday": "2001-03-04T00:00:00.000Z",
+                "tag": "🐱",
+                "group_id": 7,
+                "posts": 100,
+            }],
+            "groups": [{"id": 7, "name": "big cats", "tag": null}],
+        });
+        assert!(is_export(&export));
+        let import = parse("@test:test.local", export).unwrap();
+        let sasha = &import.members[0];
+        assert_eq!(sasha.display_name.as_deref(), Some("Sashanoraa"));
+        assert_eq!(sasha.proxy_tags, ["s:text", "[text]"]);
+        assert_eq!(sasha.birthday.as_deref(), Some("2001-03-04"));
+        assert_eq!(import.groups[0].group.name, "big-cats");
+        assert_eq!(import.groups[0].members, ["Sasha"]);
+        let unmapped: Vec<&str> = import.unmapped.iter().map(String::as_str).collect();
+        assert_eq!(unmapped, ["tupper.tag"]);
+    }
+}
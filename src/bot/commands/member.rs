@@ -10,11 +10,16 @@ use matrix_sdk::ruma::events::room::MediaSource;
 use matrix_sdk::ruma::events::{
     AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent, OriginalMessageLikeEvent,
 };
-use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::{OwnedMxcUri, UserId};
 
 use crate::bot::parser::Cmd;
+use crate::db::models::{Member, PRIVACY_FIELDS};
 use crate::db::queries;
+use crate::homeserver::HOMESERVER;
+use crate::proxy::identity::{render_template, MAX_DISPLAY_NAME_LEN};
+use crate::proxy::tags::ProxyTag;
 
+use super::switch::SWITCH_OUT;
 use super::ErrList;
 
 pub async fn exec(
@@ -37,9 +42,14 @@ pub async fn exec(
         match sub_command.as_str() {
             "displayname" | "dn" => add_display_name(cmd, room, user, &name).await?,
             "activator" | "act" => activator_cmd(cmd, room, user, &name).await?,
+            "proxy" | "px" => proxy_tag_cmd(cmd, room, user, &name).await?,
             "avatar" | "av" => add_avatar(cmd, room, user, &name, event).await?,
             "trackaccount" | "ta" => toggle_track_acc(room, user, &name).await?,
+            "pronouns" | "pn" | "description" | "desc" | "color" | "colour" | "birthday" | "bd"
+            | "notes" => set_profile_field(cmd, room, user, &name, &sub_command).await?,
+            "privacy" | "priv" => privacy_cmd(cmd, room, user, &name).await?,
             "show" | "sh" => show_member(room, user, &name).await?,
+            "preview" | "pv" => preview_display_name(room, user, &name).await?,
             "remove" | "rm" => remove_member(room, user, &name).await?,
             "rename" => rename_member(cmd, room, user, &name).await?,
             s => bail!("Unkown command {s}"),
@@ -50,8 +60,11 @@ pub async fn exec(
 
 async fn new_member(mut cmd: Cmd, room: &Joined, user: &UserId) -> anyhow::Result<()> {
     let name = cmd.pop_word().ok_or_else(|| anyhow!("Give name plz"))?;
-    queries::create_user(user.as_str()).await?;
-    queries::create_member(user.as_str(), &name).await?;
+    check_name(&name)?;
+    let member = Member::new(user.as_str(), &name);
+    if let Saved::Skipped = save_member(&member, OnConflict::Skip, false).await? {
+        bail!("This member name is already in use");
+    }
     room.send(
         RoomMessageEventContent::text_markdown(format!("Created member `{name}`")),
         None,
@@ -60,6 +73,50 @@ async fn new_member(mut cmd: Cmd, room: &Joined, user: &UserId) -> anyhow::Resul
     Ok(())
 }
 
+fn check_name(name: &str) -> anyhow::Result<()> {
+    if name == SWITCH_OUT {
+        bail!("`{SWITCH_OUT}` can't be used as a member name, `!switch {SWITCH_OUT}` clears the current fronter");
+    }
+    Ok(())
+}
+
+/// How [`save_member`] treats a member whose name is already taken.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    Skip,
+    Overwrite,
+}
+
+/// What [`save_member`] did, or would have done on a dry run.
+pub enum Saved {
+    Created,
+    Overwritten,
+    Skipped,
+}
+
+/// Creates a member from a full profile. New members and imported ones both go through here so
+/// taken names are handled the same way. Nothing is written on a `dry_run`.
+pub async fn save_member(
+    member: &Member,
+    on_conflict: OnConflict,
+    dry_run: bool,
+) -> anyhow::Result<Saved> {
+    let saved = if !queries::member_exists(&member.mxid, &member.name).await? {
+        Saved::Created
+    } else if on_conflict == OnConflict::Overwrite {
+        Saved::Overwritten
+    } else {
+        return Ok(Saved::Skipped);
+    };
+    if !dry_run {
+        queries::create_user(&member.mxid).await?;
+        queries::save_member(member)
+            .await
+            .with_context(|| format!("Error saving member {}", member.name))?;
+    }
+    Ok(saved)
+}
+
 async fn remove_member(room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
     queries::remove_member(user.as_str(), name).await?;
     room.send(
@@ -79,6 +136,7 @@ async fn rename_member(
     let new_name = cmd
         .pop_word()
         .ok_or_else(|| anyhow!("Please specify a new name"))?;
+    check_name(&new_name)?;
     queries::rename_member(user.as_str(), old_name, &new_name).await?;
     room.send(
         RoomMessageEventContent::text_markdown(format!(
@@ -109,9 +167,7 @@ async fn add_display_name(
         .await?;
     } else {
         if display_name.as_str() == "!acc" {
-            display_name = queries::get_synapse_profile(user.as_str())
-                .await?
-                .display_name;
+            display_name = HOMESERVER.profile(user.as_str()).await?.display_name;
         }
         queries::add_display_name(user.as_str(), name, &display_name).await?;
         room.send(
@@ -134,7 +190,7 @@ async fn add_avatar(
         if word.as_str() == "!clear" {
             queries::remove_avatar(user.as_str(), name).await?;
         } else if word.as_str() == "!acc" {
-            let profile = queries::get_synapse_profile(user.as_str()).await?;
+            let profile = HOMESERVER.profile(user.as_str()).await?;
             queries::add_avatar(user.as_str(), name, &profile.avatar).await?;
         } else if word.starts_with("mxc://") {
             queries::add_avatar(user.as_str(), name, &word).await?;
@@ -143,46 +199,7 @@ async fn add_avatar(
         }
         room.send(RoomMessageEventContent::text_plain("Updated avatar"), None)
             .await?;
-    } else if let Some(Relation::Reply { in_reply_to }) = &event.content.relates_to {
-        let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
-            MessageLikeEvent::Original(OriginalMessageLikeEvent {
-                content:
-                    RoomMessageEventContent {
-                        msgtype: MessageType::Image(image_event),
-                        ..
-                    },
-                ..
-            }),
-        )) = room
-            .event(&in_reply_to.event_id)
-            .await
-            .context("Error getting image event")?
-            .event
-            .deserialize()
-            .context("Error deserializing image event")?
-        else {
-            bail!("`!member [member] avatar must be sent in reply to an image");
-        };
-        let media_mxc = match image_event.source {
-            MediaSource::Plain(mxc) => mxc,
-            MediaSource::Encrypted(_) => {
-                // Reupload encrypted images unencrypted
-                let image = room
-                    .client()
-                    .media()
-                    .get_file(image_event.clone(), false)
-                    .await
-                    .context("Error getting encrypted image")?
-                    .ok_or_else(|| {
-                        anyhow!("The message this command is replying to has not image file")
-                    })?;
-                let mut upload_req = create_content::v3::Request::new(image);
-                if let Some(info) = image_event.info {
-                    upload_req.content_type = info.mimetype;
-                }
-                room.client().send(upload_req, None).await?.content_uri
-            }
-        };
+    } else if let Some(media_mxc) = replied_image(room, event).await? {
         queries::add_avatar(user.as_str(), name, media_mxc.as_str()).await?;
     } else {
         bail!("`!member [member] avatar must be sent in reply to an image");
@@ -190,20 +207,298 @@ async fn add_avatar(
     Ok(())
 }
 
+/// Gets the image `event` is replying to, or `None` if it isn't a reply. Encrypted images are
+/// reuploaded unencrypted so they can be used as avatars.
+pub async fn replied_image(
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<Option<OwnedMxcUri>> {
+    let Some(Relation::Reply { in_reply_to }) = &event.content.relates_to else {
+        return Ok(None);
+    };
+    let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(OriginalMessageLikeEvent {
+            content:
+                RoomMessageEventContent {
+                    msgtype: MessageType::Image(image_event),
+                    ..
+                },
+            ..
+        }),
+    )) = room
+        .event(&in_reply_to.event_id)
+        .await
+        .context("Error getting image event")?
+        .event
+        .deserialize()
+        .context("Error deserializing image event")?
+    else {
+        bail!("Avatars must be sent in reply to an image");
+    };
+    let media_mxc = match image_event.source {
+        MediaSource::Plain(mxc) => mxc,
+        MediaSource::Encrypted(_) => {
+            // Reupload encrypted images unencrypted
+            let image = room
+                .client()
+                .media()
+                .get_file(image_event.clone(), false)
+                .await
+                .context("Error getting encrypted image")?
+                .ok_or_else(|| {
+                    anyhow!("The message this command is replying to has not image file")
+                })?;
+            let mut upload_req = create_content::v3::Request::new(image);
+            if let Some(info) = image_event.info {
+                upload_req.content_type = info.mimetype;
+            }
+            room.client().send(upload_req, None).await?.content_uri
+        }
+    };
+    Ok(Some(media_mxc))
+}
+
 async fn show_member(room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
     let member = queries::get_member(user.as_str(), name).await?;
-    let message = format!(
-        "## {}\n\n---\n\nDisplay name: {}\n\nAvatar: `{}`\n\nActivators: `{}`",
+    let mut message = format!(
+        "## {}\n\n",
+        member.display_name.as_deref().unwrap_or(&member.name)
+    );
+    if let Some(pronouns) = &member.pronouns {
+        message += &format!("*{pronouns}*\n\n");
+    }
+    if let Some(description) = &member.description {
+        message += &format!("{description}\n\n");
+    }
+    message += &format!(
+        "---\n\nName: `{}`\n\nDisplay name: {}\n\nAvatar: `{}`\n\nActivators: `{}`\n\nProxy tags: `{}`",
         member.name,
         member.display_name.as_deref().unwrap_or("`not set`"),
         member.avatar.as_deref().unwrap_or("not set"),
         member.activators.join(", "),
+        member.proxy_tags.join(", "),
     );
+    if let Some(color) = &member.color {
+        message += &format!("\n\nColour: <font data-mx-color=\"#{color}\">■</font> `#{color}`");
+    }
+    if let Some(birthday) = &member.birthday {
+        message += &format!("\n\nBirthday: {birthday}");
+    }
+    if let Some(notes) = &member.notes {
+        message += &format!("\n\nNotes: {notes}");
+    }
+    message += &format!("\n\nPrivacy: {}", describe_privacy(&member));
     room.send(RoomMessageEventContent::text_markdown(message), None)
         .await?;
     Ok(())
 }
 
+fn describe_privacy(member: &Member) -> String {
+    if member.private {
+        "private, hidden from other users".to_owned()
+    } else if member.private_fields.is_empty() {
+        "public".to_owned()
+    } else {
+        format!("public, hiding `{}`", member.private_fields.join(", "))
+    }
+}
+
+/// Maps the names fields are given in commands to the ones stored in `private_fields`.
+fn privacy_field(field: &str) -> Option<&'static str> {
+    let field = match field {
+        "dn" => "displayname",
+        "av" => "avatar",
+        "pn" => "pronouns",
+        "desc" => "description",
+        "colour" => "color",
+        "bd" => "birthday",
+        field => field,
+    };
+    PRIVACY_FIELDS.iter().copied().find(|f| *f == field)
+}
+
+async fn privacy_cmd(mut cmd: Cmd, room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
+    let user = user.as_str();
+    let msg = match (cmd.pop_word(), cmd.pop_word()) {
+        (None, _) => {
+            let member = queries::get_member(user, name).await?;
+            format!("`{name}` is {}", describe_privacy(&member))
+        }
+        (Some(level), None) => {
+            let private = parse_privacy(&level)?;
+            queries::set_member_private(user, name, private).await?;
+            format!("`{name}` is now {level}")
+        }
+        (Some(field), Some(level)) => {
+            let field = privacy_field(&field).ok_or_else(|| {
+                anyhow!(
+                    "Unknown field `{field}`, must be one of `{}`",
+                    PRIVACY_FIELDS.join("`, `")
+                )
+            })?;
+            let private = parse_privacy(&level)?;
+            queries::set_member_field_private(user, name, field, private).await?;
+            format!("`{name}`'s {field} is now {level}")
+        }
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await?;
+    Ok(())
+}
+
+fn parse_privacy(level: &str) -> anyhow::Result<bool> {
+    match level {
+        "public" => Ok(false),
+        "private" => Ok(true),
+        s => bail!("Unknown privacy level `{s}`, must be `public` or `private`"),
+    }
+}
+
+async fn set_profile_field(
+    cmd: Cmd,
+    room: &Joined,
+    user: &UserId,
+    name: &str,
+    field: &str,
+) -> anyhow::Result<()> {
+    let value = cmd.into_string();
+    if value.is_empty() {
+        bail!("Please give a value for the {field}, or `!clear` to clear it");
+    }
+    let value = match value.as_str() {
+        "!clear" | "!cl" => None,
+        _ => Some(value),
+    };
+    let user = user.as_str();
+    let field = match field {
+        "pronouns" | "pn" => {
+            queries::set_member_pronouns(user, name, value.as_deref()).await?;
+            "pronouns"
+        }
+        "description" | "desc" => {
+            queries::set_member_description(user, name, value.as_deref()).await?;
+            "description"
+        }
+        "color" | "colour" => {
+            let color = value
+                .as_deref()
+                .map(|color| {
+                    parse_color(color).ok_or_else(|| {
+                        anyhow!("`{color}` is not a hex colour, use something like `#ff8800`")
+                    })
+                })
+                .transpose()?;
+            queries::set_member_color(user, name, color.as_deref()).await?;
+            "colour"
+        }
+        "birthday" | "bd" => {
+            let birthday = value
+                .as_deref()
+                .map(|birthday| {
+                    parse_birthday(birthday).ok_or_else(|| {
+                        anyhow!("`{birthday}` is not a date, use `YYYY-MM-DD` or `MM-DD`")
+                    })
+                })
+                .transpose()?;
+            queries::set_member_birthday(user, name, birthday.as_deref()).await?;
+            "birthday"
+        }
+        "notes" => {
+            queries::set_member_notes(user, name, value.as_deref()).await?;
+            "notes"
+        }
+        s => bail!("Unkown field {s}"),
+    };
+    let msg = match value {
+        Some(_) => format!("Updated {field} for {name}"),
+        None => format!("Cleared {field} for {name}"),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+/// Normalises a hex colour like `#FF8800` or `f80` to `ff8800`.
+pub fn parse_color(color: &str) -> Option<String> {
+    let color = color.strip_prefix('#').unwrap_or(color);
+    if !color.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match color.len() {
+        6 => Some(color.to_ascii_lowercase()),
+        3 => Some(
+            color
+                .chars()
+                .flat_map(|c| [c, c])
+                .collect::<String>()
+                .to_ascii_lowercase(),
+        ),
+        _ => None,
+    }
+}
+
+/// Checks a birthday is `YYYY-MM-DD` or `MM-DD`. PluralKit's `0004-MM-DD` for birthdays
+/// without a year is turned into `MM-DD`.
+pub fn parse_birthday(birthday: &str) -> Option<String> {
+    let parts: Vec<&str> = birthday.split('-').collect();
+    let (year, month, day) = match parts[..] {
+        [year, month, day] if year.len() == 4 => (Some(year), month, day),
+        [month, day] => (None, month, day),
+        _ => return None,
+    };
+    let year: Option<u32> = year.map(str::parse).transpose().ok()?;
+    let (month, day): (u32, u32) = (month.parse().ok()?, day.parse().ok()?);
+    // The `0004` placeholder is a leap year, so Feb 29 without a year is kept
+    let leap = match year {
+        Some(year) => year % 4 == 0 && (year % 100 != 0 || year % 400 == 0),
+        None => true,
+    };
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days).contains(&day) {
+        return None;
+    }
+    match year {
+        Some(year) if year != 4 => Some(format!("{year:04}-{month:02}-{day:02}")),
+        _ => Some(format!("{month:02}-{day:02}")),
+    }
+}
+
+async fn preview_display_name(room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
+    let member = queries::get_member(user.as_str(), name).await?;
+    let system = queries::get_system(user.as_str()).await?;
+    let base_name = member.display_name.as_deref().unwrap_or(&member.name);
+    let msg = match &system.template {
+        Some(template) => {
+            let pronouns = member.pronouns.as_deref().or(system.pronouns.as_deref());
+            let rendered = render_template(template, base_name, pronouns, &system);
+            let len = rendered.chars().count();
+            if len > MAX_DISPLAY_NAME_LEN {
+                format!(
+                    "`{rendered}` is {len} characters long, over the limit of {MAX_DISPLAY_NAME_LEN}. \
+                    `{base_name}` will be used instead"
+                )
+            } else {
+                format!("**{name}** will show up as `{rendered}`")
+            }
+        }
+        None => format!(
+            "**{name}** will show up as `{base_name}`\n\n\
+            Set a display name template with `!system set template [template]`"
+        ),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
 async fn activator_cmd(
     mut cmd: Cmd,
     room: &Joined,
@@ -229,6 +524,13 @@ async fn activator_cmd(
             if activator.starts_with('!') {
                 bail!("activation sequence cannot start with `!`");
             }
+            // Covers group activators too, a clash would make switching ambiguous
+            if queries::get_members_from_activators(user.as_str(), &[activator.clone()])
+                .await?
+                .is_some()
+            {
+                bail!("`{activator}` is already in use");
+            }
             queries::add_activator(user.as_str(), name, &activator)
                 .await
                 .context("Error adding activator")?;
@@ -254,6 +556,44 @@ async fn activator_cmd(
     Ok(())
 }
 
+async fn proxy_tag_cmd(
+    mut cmd: Cmd,
+    room: &Joined,
+    user: &UserId,
+    name: &str,
+) -> anyhow::Result<()> {
+    let sub_command = cmd
+        .pop_word()
+        .ok_or_else(|| anyhow!("Please specify a sub-command"))?;
+    let tag = cmd.into_string();
+    if tag.is_empty() {
+        bail!("!member [member] proxy {sub_command} needs a proxy tag as an arguement");
+    }
+    match sub_command.as_str() {
+        "add" => {
+            if ProxyTag::parse(&tag).is_none() {
+                bail!("Proxy tags must contain the word `text` with a prefix and/or suffix around it, e.g. `s:text` or `[text]`");
+            }
+            queries::add_proxy_tag(user.as_str(), name, &tag)
+                .await
+                .context("Error adding proxy tag")?;
+            let msg = format!("Added proxy tag `{}` to {}", tag, name);
+            room.send(RoomMessageEventContent::text_markdown(msg), None)
+                .await
+                .context("Error sending reply")?;
+        }
+        "remove" | "rm" => {
+            queries::remove_proxy_tag(user.as_str(), name, &tag).await?;
+            let msg = format!("Removed proxy tag `{}` from {}", tag, name);
+            room.send(RoomMessageEventContent::text_markdown(msg), None)
+                .await
+                .context("Error sending reply")?;
+        }
+        unknown => bail!("Unkown sub-command `{unknown}`"),
+    }
+    Ok(())
+}
+
 async fn toggle_track_acc(room: &Joined, user: &UserId, name: &str) -> anyhow::Result<()> {
     let msg = if queries::toggle_tracking(user.as_str(), name)
         .await
@@ -269,3 +609,30 @@ async fn toggle_track_acc(room: &Joined, user: &UserId, name: &str) -> anyhow::R
         .context("Error sending reply")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_birthday, parse_color};
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#FF8800").as_deref(), Some("ff8800"));
+        assert_eq!(parse_color("f80").as_deref(), Some("ff8800"));
+        assert_eq!(parse_color("#ff88"), None);
+        assert_eq!(parse_color("orange"), None);
+    }
+
+    #[test]
+    fn birthdays() {
+        assert_eq!(parse_birthday("2001-3-4").as_deref(), Some("2001-03-04"));
+        assert_eq!(parse_birthday("0004-02-29").as_deref(), Some("02-29"));
+        assert_eq!(parse_birthday("12-25").as_deref(), Some("12-25"));
+        assert_eq!(parse_birthday("2001-04-31"), None);
+        assert_eq!(parse_birthday("2000-02-29").as_deref(), Some("2000-02-29"));
+        assert_eq!(parse_birthday("2001-02-29"), None);
+        assert_eq!(parse_birthday("1900-02-29"), None);
+        assert_eq!(parse_birthday("02-29").as_deref(), Some("02-29"));
+        assert_eq!(parse_birthday("13-01"), None);
+        assert_eq!(parse_birthday("tuesday"), None);
+    }
+}
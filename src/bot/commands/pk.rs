@@ -0,0 +1,186 @@
+//! Links systems to PluralKit and keeps them in sync with it.
+
+mod api;
+mod sync;
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use matrix_sdk::Client;
+
+use crate::bot::parser::Cmd;
+use crate::config::CONFIG;
+use crate::db::queries;
+
+use self::api::PkApi;
+use self::sync::{sync_user, SyncReport};
+
+use super::ErrList;
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    client: &Client,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    if CONFIG.pluralkit.is_none() {
+        bail!("PluralKit sync isn't enabled on this server");
+    }
+    let user = event.sender.as_str();
+    match cmd.pop_word().as_deref() {
+        None | Some("status") => show_status(room, user).await?,
+        Some("link") => link(cmd, room, event).await?,
+        Some("unlink") => unlink(room, user).await?,
+        Some("sync") => {
+            let report = sync_user(client, user).await?;
+            room.send(
+                RoomMessageEventContent::text_markdown(format_report(&report)),
+                None,
+            )
+            .await
+            .context("Error sending reply")?;
+        }
+        Some(s) => bail!("Unkown command {s}"),
+    }
+    Ok(vec![])
+}
+
+async fn link(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<()> {
+    let token = cmd
+        .pop_word()
+        .ok_or_else(|| anyhow!("Please give your PluralKit token, get it with `pk;token`"))?;
+    // Don't leave the token sitting in the DM
+    if let Err(e) = room
+        .redact(&event.event_id, Some("Contains a PluralKit token"), None)
+        .await
+    {
+        tracing::warn!("Error redacting PluralKit token: {e:?}");
+    }
+    let pk = CONFIG
+        .pluralkit
+        .as_ref()
+        .ok_or_else(|| anyhow!("PluralKit sync isn't enabled on this server"))?;
+    let system = PkApi::new(&pk.api_url, &token).get_system().await?;
+    let user = event.sender.as_str();
+    queries::create_user(user).await?;
+    queries::link_pk(user, &token, &system.id)
+        .await
+        .context("Error saving PluralKit link")?;
+    let name = system
+        .name
+        .map(|name| format!("{name} (`{}`)", system.id))
+        .unwrap_or_else(|| format!("`{}`", system.id));
+    room.send(
+        RoomMessageEventContent::text_markdown(format!(
+            "Linked to PluralKit system {name}. Members are pulled from PluralKit and new switches \
+            are pushed to it every {} minutes, send `!pk sync` to sync now",
+            pk.sync_interval_secs.max(60) / 60
+        )),
+        None,
+    )
+    .await
+    .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn unlink(room: &Joined, user: &str) -> anyhow::Result<()> {
+    if !queries::unlink_pk(user).await? {
+        bail!("This account isn't linked to PluralKit");
+    }
+    room.send(
+        RoomMessageEventContent::text_plain("Unlinked from PluralKit"),
+        None,
+    )
+    .await
+    .context("Error sending reply")?;
+    Ok(())
+}
+
+async fn show_status(room: &Joined, user: &str) -> anyhow::Result<()> {
+    let msg = match queries::get_pk_link(user).await? {
+        None => "This account isn't linked to PluralKit, link it with `!pk link [token]`. \
+            Get your token by sending `pk;token` to PluralKit"
+            .to_owned(),
+        Some(link) => {
+            let members = queries::get_pk_member_syncs(user).await?;
+            let mut msg = format!("#### Linked to PluralKit system `{}`\n\n", link.system_id);
+            msg += &format!(
+                "- Last synced: {}\n",
+                link.last_synced.as_deref().unwrap_or("never")
+            );
+            if let Some(until) = &link.switches_pushed_until {
+                msg += &format!("- Switches pushed until: {until}\n");
+            }
+            msg += &format!("- Synced members: {}\n", members.len());
+            for member in members {
+                msg += &format!(
+                    "  - {} (`{}`), last synced {}\n",
+                    member.member, member.pk_id, member.synced_at
+                );
+            }
+            msg
+        }
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+fn format_report(report: &SyncReport) -> String {
+    let mut msg = "#### Synced with PluralKit\n\n".to_owned();
+    if !report.created.is_empty() {
+        msg += &format!("- Created: {}\n", report.created.join(", "));
+    }
+    if !report.updated.is_empty() {
+        msg += &format!("- Updated: {}\n", report.updated.join(", "));
+    }
+    if report.created.is_empty() && report.updated.is_empty() {
+        msg += "- No member changes\n";
+    }
+    msg += &format!("- Switches pushed: {}\n", report.switches);
+    for conflict in &report.conflicts {
+        msg += &format!("- {conflict}\n");
+    }
+    for note in &report.notes {
+        msg += &format!("- {note}\n");
+    }
+    msg
+}
+
+/// Syncs every linked system on the configured interval. Does nothing when sync isn't enabled.
+pub async fn sync_daemon(client: Client) {
+    let Some(pk) = &CONFIG.pluralkit else {
+        return;
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(pk.sync_interval_secs.max(60)));
+    loop {
+        interval.tick().await;
+        let users = match queries::list_pk_links().await {
+            Ok(users) => users,
+            Err(e) => {
+                tracing::error!("Error getting PluralKit links: {e:#}");
+                continue;
+            }
+        };
+        for user in users {
+            tracing::debug!("Syncing {user} with PluralKit");
+            match sync_user(&client, &user).await {
+                Ok(report) => {
+                    for note in report.conflicts.iter().chain(&report.notes) {
+                        tracing::info!("PluralKit sync for {user}: {note}");
+                    }
+                }
+                Err(e) => tracing::error!("Error syncing {user} with PluralKit: {e:#}"),
+            }
+        }
+    }
+}
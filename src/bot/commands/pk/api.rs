@@ -0,0 +1,219 @@
+//! A small client for the parts of PluralKit's v2 REST API that sync uses.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use once_cell::sync::Lazy;
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// PluralKit's error code for a switch with the same members as the current one.
+const SAME_SWITCH: i64 = 40004;
+
+static HTTP: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .user_agent(concat!("plural-kitty/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Error building HTTP client")
+});
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PkSystem {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PkMember {
+    pub id: String,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub pronouns: Option<String>,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    /// `YYYY-MM-DD`, with the year `0004` if it isn't known
+    pub birthday: Option<String>,
+    pub avatar_url: Option<String>,
+    pub webhook_avatar_url: Option<String>,
+}
+
+pub struct PkApi {
+    base_url: String,
+    token: String,
+}
+
+impl PkApi {
+    pub fn new(base_url: &str, token: &str) -> Self {
+        PkApi {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            token: token.to_owned(),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        HTTP.request(method, format!("{}{path}", self.base_url))
+            .header(reqwest::header::AUTHORIZATION, &self.token)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        let response = self
+            .request(Method::GET, path)
+            .send()
+            .await
+            .with_context(|| format!("Error contacting PluralKit at {}", self.base_url))?;
+        check_status(response)
+            .await?
+            .json()
+            .await
+            .context("PluralKit sent an invalid response")
+    }
+
+    /// The system the token belongs to.
+    pub async fn get_system(&self) -> anyhow::Result<PkSystem> {
+        self.get("/systems/@me").await
+    }
+
+    pub async fn get_members(&self) -> anyhow::Result<Vec<PkMember>> {
+        self.get("/systems/@me/members").await
+    }
+
+    /// Logs a switch to `members`, given by their PluralKit IDs, at `timestamp` (RFC 3339).
+    /// Switches to the members that are already fronting are quietly skipped.
+    pub async fn post_switch(&self, timestamp: &str, members: &[String]) -> anyhow::Result<()> {
+        let response = self
+            .request(Method::POST, "/systems/@me/switches")
+            .json(&json!({ "timestamp": timestamp, "members": members }))
+            .send()
+            .await
+            .with_context(|| format!("Error contacting PluralKit at {}", self.base_url))?;
+        if response.status() == StatusCode::BAD_REQUEST {
+            let error: Value = response.json().await.unwrap_or_default();
+            if error.get("code").and_then(Value::as_i64) == Some(SAME_SWITCH) {
+                return Ok(());
+            }
+            bail!("PluralKit rejected the switch: {}", error_message(&error));
+        }
+        check_status(response).await?;
+        Ok(())
+    }
+}
+
+async fn check_status(response: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::UNAUTHORIZED => {
+            bail!("PluralKit rejected the token, link again with `!pk link [token]`")
+        }
+        StatusCode::TOO_MANY_REQUESTS => bail!("PluralKit is rate limiting requests"),
+        status => {
+            let error: Value = response.json().await.unwrap_or_default();
+            bail!("PluralKit returned {status}: {}", error_message(&error))
+        }
+    }
+}
+
+fn error_message(error: &Value) -> &str {
+    error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("no message")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+
+    use super::PkApi;
+    use crate::stand_in;
+
+    type Switches = Arc<Mutex<Vec<Value>>>;
+
+    fn authorized(headers: &HeaderMap) -> Result<(), StatusCode> {
+        match headers.get("authorization") {
+            Some(token) if token == "token" => Ok(()),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    async fn system(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+        authorized(&headers)?;
+        Ok(Json(json!({"id": "abcde", "name": "The Kitties"})))
+    }
+
+    async fn members(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+        authorized(&headers)?;
+        Ok(Json(json!([{
+            "id": "aaaaa",
+            "name": "Sasha",
+            "pronouns": "ze/zir",
+            "birthday": "0004-03-04",
+            "privacy": null,
+        }])))
+    }
+
+    async fn switch(
+        State(switches): State<Switches>,
+        headers: HeaderMap,
+        Json(body): Json<Value>,
+    ) -> Result<(StatusCode, Json<Value>), StatusCode> {
+        authorized(&headers)?;
+        let mut switches = switches.lock().unwrap();
+        if switches.last().map(|s| &s["members"]) == Some(&body["members"]) {
+            let error =
+                json!({"code": 40004, "message": "Member list identical to current fronter list"});
+            return Ok((StatusCode::BAD_REQUEST, Json(error)));
+        }
+        switches.push(body.clone());
+        Ok((StatusCode::OK, Json(body)))
+    }
+
+    /// Starts a stand-in for PluralKit's API on a free local port and returns its base URL.
+    fn stand_in(switches: Switches) -> String {
+        let app = Router::new()
+            .route("/systems/@me", get(system))
+            .route("/systems/@me/members", get(members))
+            .route("/systems/@me/switches", post(switch))
+            .with_state(switches);
+        stand_in::serve(app)
+    }
+
+    #[tokio::test]
+    async fn stand_in_server() {
+        let switches = Switches::default();
+        let base_url = stand_in(switches.clone());
+
+        let err = PkApi::new(&base_url, "wrong")
+            .get_system()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("rejected the token"));
+
+        let api = PkApi::new(&base_url, "token");
+        assert_eq!(api.get_system().await.unwrap().id, "abcde");
+        let members = api.get_members().await.unwrap();
+        assert_eq!(members[0].name, "Sasha");
+        assert_eq!(members[0].birthday.as_deref(), Some("0004-03-04"));
+
+        let sasha = vec!["aaaaa".to_owned()];
+        api.post_switch("2026-10-18T12:00:00Z", &sasha)
+            .await
+            .unwrap();
+        api.post_switch("2026-10-18T13:00:00Z", &sasha)
+            .await
+            .unwrap();
+        api.post_switch("2026-10-18T14:00:00Z", &[]).await.unwrap();
+        let switches = switches.lock().unwrap();
+        assert_eq!(switches.len(), 2);
+        assert_eq!(switches[0]["timestamp"], "2026-10-18T12:00:00Z");
+        assert_eq!(switches[1]["members"], json!([]));
+    }
+}
@@ -0,0 +1,375 @@
+//! Pulls members from PluralKit and pushes switches to it.
+//!
+//! Members are matched by the PluralKit ID they were last synced with, or by name the first time.
+//! Each sync stores a snapshot of the member's fields on both sides, so the next sync can tell
+//! which side changed a field: changes made only in PluralKit are pulled, changes made only in
+//! Plural Kitty are kept, and fields changed on both sides keep Plural Kitty's value and are
+//! reported as conflicts.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use matrix_sdk::Client;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::bot::commands::import::reupload;
+use crate::bot::commands::member::{parse_birthday, parse_color, save_member, OnConflict};
+use crate::config::CONFIG;
+use crate::db::models::Member;
+use crate::db::queries;
+
+use super::api::{PkApi, PkMember};
+
+/// PluralKit only allows a few writes a second.
+const SWITCH_PUSH_DELAY: Duration = Duration::from_millis(500);
+
+/// Held while a user's system is synced, which keeps the daemon and `!pk sync` from racing.
+static SYNC_LOCKS: Lazy<Mutex<HashMap<String, Arc<Mutex<()>>>>> = Lazy::new(Default::default);
+
+/// The member fields kept in sync.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fields {
+    pub display_name: Option<String>,
+    pub avatar: Option<String>,
+    pub pronouns: Option<String>,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub birthday: Option<String>,
+}
+
+impl Fields {
+    fn from_member(member: &Member) -> Self {
+        Fields {
+            display_name: member.display_name.clone(),
+            avatar: member.avatar.clone(),
+            pronouns: member.pronouns.clone(),
+            description: member.description.clone(),
+            color: member.color.clone(),
+            birthday: member.birthday.clone(),
+        }
+    }
+
+    /// A PluralKit member's fields in Plural Kitty's formats. The avatar is still PluralKit's URL.
+    fn from_pk(member: &PkMember) -> Self {
+        Fields {
+            display_name: member.display_name.clone(),
+            avatar: member
+                .webhook_avatar_url
+                .clone()
+                .or_else(|| member.avatar_url.clone()),
+            pronouns: member.pronouns.clone(),
+            description: member.description.clone(),
+            color: member.color.as_deref().and_then(parse_color),
+            birthday: member.birthday.as_deref().and_then(parse_birthday),
+        }
+    }
+
+    fn apply(&self, member: &mut Member) {
+        member.display_name = self.display_name.clone();
+        member.avatar = self.avatar.clone();
+        member.pronouns = self.pronouns.clone();
+        member.description = self.description.clone();
+        member.color = self.color.clone();
+        member.birthday = self.birthday.clone();
+    }
+
+    fn fields(&self) -> [(&'static str, &Option<String>); 6] {
+        [
+            ("display name", &self.display_name),
+            ("avatar", &self.avatar),
+            ("pronouns", &self.pronouns),
+            ("description", &self.description),
+            ("colour", &self.color),
+            ("birthday", &self.birthday),
+        ]
+    }
+
+    fn fields_mut(&mut self) -> [&mut Option<String>; 6] {
+        [
+            &mut self.display_name,
+            &mut self.avatar,
+            &mut self.pronouns,
+            &mut self.description,
+            &mut self.color,
+            &mut self.birthday,
+        ]
+    }
+}
+
+/// A member's fields on both sides as of the last sync.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub local: Fields,
+    pub remote: Fields,
+}
+
+pub struct Merged {
+    pub fields: Fields,
+    /// Fields changed on both sides, Plural Kitty's values were kept
+    pub conflicts: Vec<&'static str>,
+}
+
+/// Works out a member's fields from what they were at the last sync and what they are now on each
+/// side.
+pub fn merge(base: &Snapshot, local: &Fields, remote: &Fields) -> Merged {
+    let mut fields = local.clone();
+    let mut conflicts = vec![];
+    let changes = base
+        .local
+        .fields()
+        .into_iter()
+        .zip(base.remote.fields())
+        .zip(local.fields())
+        .zip(remote.fields());
+    for (field, ((((name, base_local), (_, base_remote)), (_, local)), (_, remote))) in
+        fields.fields_mut().into_iter().zip(changes)
+    {
+        if remote == base_remote {
+            continue;
+        }
+        if local == base_local {
+            *field = remote.clone();
+        } else if local != remote {
+            conflicts.push(name);
+        }
+    }
+    Merged { fields, conflicts }
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub switches: usize,
+    pub notes: Vec<String>,
+}
+
+/// Pulls the user's members from PluralKit and pushes the switches they made since the last sync.
+pub async fn sync_user(client: &Client, mxid: &str) -> anyhow::Result<SyncReport> {
+    let Some(pk) = &CONFIG.pluralkit else {
+        bail!("PluralKit sync isn't enabled on this server");
+    };
+    let sync_lock = SYNC_LOCKS
+        .lock()
+        .await
+        .entry(mxid.to_owned())
+        .or_default()
+        .clone();
+    let _lock = sync_lock.lock().await;
+    let Some(link) = queries::get_pk_link(mxid).await? else {
+        bail!("This account isn't linked to PluralKit, link it with `!pk link [token]`");
+    };
+    let api = PkApi::new(&pk.api_url, &link.token);
+    let mut report = SyncReport::default();
+
+    let remote_members = api.get_members().await?;
+    let mut synced: HashMap<String, (String, Snapshot)> = HashMap::new();
+    let mut linked = HashSet::new();
+    for sync in queries::get_pk_member_syncs(mxid).await? {
+        let snapshot = serde_json::from_str(&sync.snapshot).unwrap_or_default();
+        linked.insert(sync.member.clone());
+        synced.insert(sync.pk_id, (sync.member, snapshot));
+    }
+    let local_names = queries::list_members(mxid).await?;
+    let mut pk_ids = HashMap::new();
+    for remote in remote_members {
+        let name = match synced.remove(&remote.id) {
+            Some((name, snapshot)) => {
+                pull_member(client, mxid, &name, &remote, snapshot, &mut report).await?;
+                name
+            }
+            None => {
+                let name = remote.name.split_whitespace().collect::<Vec<_>>().join("-");
+                if name.is_empty() {
+                    continue;
+                } else if linked.contains(&name) {
+                    report.notes.push(format!(
+                        "PluralKit member `{}` wasn't synced, `{name}` is already linked to another member",
+                        remote.name
+                    ));
+                    continue;
+                } else if local_names.contains(&name) {
+                    pull_member(
+                        client,
+                        mxid,
+                        &name,
+                        &remote,
+                        Snapshot::default(),
+                        &mut report,
+                    )
+                    .await?;
+                } else {
+                    create_member(client, mxid, &name, &remote, &mut report).await?;
+                }
+                linked.insert(name.clone());
+                name
+            }
+        };
+        pk_ids.insert(name, remote.id);
+    }
+
+    let switches = queries::get_switches_after(mxid, link.switches_pushed_until.as_deref()).await?;
+    let mut pushed_until = None;
+    for (members, started_at) in switches {
+        let ids: Vec<String> = members
+            .iter()
+            .filter_map(|name| pk_ids.get(name).cloned())
+            .collect();
+        // A switch to members PluralKit doesn't know isn't a switch out
+        if ids.is_empty() && !members.is_empty() {
+            pushed_until = Some(started_at);
+            continue;
+        }
+        if let Err(e) = api.post_switch(&started_at, &ids).await {
+            queries::set_pk_synced(mxid, pushed_until.as_deref()).await?;
+            return Err(e.context("Error pushing switches"));
+        }
+        report.switches += 1;
+        pushed_until = Some(started_at);
+        tokio::time::sleep(SWITCH_PUSH_DELAY).await;
+    }
+    queries::set_pk_synced(mxid, pushed_until.as_deref()).await?;
+    Ok(report)
+}
+
+async fn pull_member(
+    client: &Client,
+    mxid: &str,
+    name: &str,
+    remote: &PkMember,
+    base: Snapshot,
+    report: &mut SyncReport,
+) -> anyhow::Result<()> {
+    let mut member = queries::get_member(mxid, name).await?;
+    let local = Fields::from_member(&member);
+    let mut remote_fields = Fields::from_pk(remote);
+    let mut merged = merge(&base, &local, &remote_fields);
+    if merged.fields.avatar != local.avatar {
+        if let Some(url) = &merged.fields.avatar {
+            match reupload(client, url).await {
+                Ok(mxc) => merged.fields.avatar = Some(mxc),
+                Err(e) => {
+                    report
+                        .notes
+                        .push(format!("Couldn't copy `{name}`'s avatar: {e:#}"));
+                    merged.fields.avatar = local.avatar.clone();
+                    // Try again next sync
+                    remote_fields.avatar = base.remote.avatar.clone();
+                }
+            }
+        }
+    }
+    if merged.fields != local {
+        merged.fields.apply(&mut member);
+        queries::update_member_profile(&member).await?;
+        report.updated.push(name.to_owned());
+    }
+    for field in merged.conflicts {
+        report.conflicts.push(format!(
+            "`{name}`'s {field} was changed in both, Plural Kitty's was kept"
+        ));
+    }
+    let snapshot = Snapshot {
+        local: merged.fields,
+        remote: remote_fields,
+    };
+    save_snapshot(mxid, name, &remote.id, &snapshot).await
+}
+
+async fn create_member(
+    client: &Client,
+    mxid: &str,
+    name: &str,
+    remote: &PkMember,
+    report: &mut SyncReport,
+) -> anyhow::Result<()> {
+    let mut remote_fields = Fields::from_pk(remote);
+    let mut member = Member::new(mxid, name);
+    remote_fields.apply(&mut member);
+    if member.display_name.is_none() && name != remote.name {
+        member.display_name = Some(remote.name.clone());
+    }
+    if let Some(url) = &member.avatar {
+        member.avatar = match reupload(client, url).await {
+            Ok(mxc) => Some(mxc),
+            Err(e) => {
+                report
+                    .notes
+                    .push(format!("Couldn't copy `{name}`'s avatar: {e:#}"));
+                None
+            }
+        };
+    }
+    queries::create_user(mxid).await?;
+    save_member(&member, OnConflict::Skip, false).await?;
+    report.created.push(name.to_owned());
+    if member.avatar.is_none() {
+        // Try again next sync
+        remote_fields.avatar = None;
+    }
+    let snapshot = Snapshot {
+        local: Fields::from_member(&member),
+        remote: remote_fields,
+    };
+    save_snapshot(mxid, name, &remote.id, &snapshot).await
+}
+
+async fn save_snapshot(
+    mxid: &str,
+    name: &str,
+    pk_id: &str,
+    snapshot: &Snapshot,
+) -> anyhow::Result<()> {
+    let snapshot = serde_json::to_string(snapshot).context("Error serialising sync snapshot")?;
+    queries::set_pk_member_synced(mxid, name, pk_id, &snapshot)
+        .await
+        .with_context(|| format!("Error saving sync marker for {name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, Fields, Snapshot};
+
+    fn fields(pronouns: Option<&str>, description: Option<&str>, color: Option<&str>) -> Fields {
+        Fields {
+            pronouns: pronouns.map(str::to_owned),
+            description: description.map(str::to_owned),
+            color: color.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn three_way_merge() {
+        let base = Snapshot {
+            local: fields(Some("ze/zir"), Some("cat"), Some("ff8800")),
+            remote: fields(Some("ze/zir"), Some("cat"), Some("ff8800")),
+        };
+        // Pronouns changed in PluralKit, description in both, colour only here
+        let local = fields(Some("ze/zir"), Some("big cat"), Some("000000"));
+        let remote = fields(Some("they/them"), Some("small cat"), Some("ff8800"));
+        let merged = merge(&base, &local, &remote);
+        assert_eq!(
+            merged.fields,
+            fields(Some("they/them"), Some("big cat"), Some("000000"))
+        );
+        assert_eq!(merged.conflicts, ["description"]);
+    }
+
+    #[test]
+    fn first_sync() {
+        let local = fields(Some("ze/zir"), None, Some("ff8800"));
+        let remote = fields(Some("they/them"), Some("cat"), Some("ff8800"));
+        let merged = merge(&Snapshot::default(), &local, &remote);
+        assert_eq!(
+            merged.fields,
+            fields(Some("ze/zir"), Some("cat"), Some("ff8800"))
+        );
+        assert_eq!(merged.conflicts, ["pronouns"]);
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, bail, Context};
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::{RoomId, UserId};
+use matrix_sdk::Client;
+
+use crate::bot::parser::Cmd;
+use crate::db::queries;
+use crate::homeserver::HOMESERVER;
+
+use super::ErrList;
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    client: &Client,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let Some(room_id) = cmd.pop_room_id(client).await? else {
+        bail!("Please specify a room, e.g. `!room [room] front [member]`");
+    };
+    let sub_command = cmd
+        .pop_word()
+        .ok_or_else(|| anyhow!("Please specify a subcommand"))?;
+    match sub_command.as_str() {
+        "front" | "f" => room_front(cmd, room, &event.sender, &room_id).await?,
+        s => bail!("Unkown command {s}"),
+    }
+    Ok(vec![])
+}
+
+async fn room_front(
+    mut cmd: Cmd,
+    room: &Joined,
+    user: &UserId,
+    room_id: &RoomId,
+) -> anyhow::Result<()> {
+    let room_name = HOMESERVER
+        .room_alias(room_id.as_str())
+        .await
+        .unwrap_or_else(|_| room_id.as_str().to_owned());
+    let msg = match cmd.pop_word() {
+        None => match queries::get_room_fronter(user.as_str(), room_id.as_str()).await? {
+            Some(member) => format!("**{}** always fronts in {room_name}", member.name),
+            None => format!("No fronter set for {room_name}"),
+        },
+        Some(word) if word == "!clear" || word == "!cl" => {
+            if queries::remove_room_fronter(user.as_str(), room_id.as_str()).await? {
+                format!("Cleared fronter for {room_name}")
+            } else {
+                format!("No fronter set for {room_name}")
+            }
+        }
+        Some(name) => {
+            if !queries::member_exists(user.as_str(), &name).await? {
+                bail!("Member {name} does not exist.\n\nCreate this member with `!m new {name}`");
+            }
+            queries::set_room_fronter(user.as_str(), room_id.as_str(), &name)
+                .await
+                .context("Error setting room fronter")?;
+            format!("**{name}** will now always front in {room_name}")
+        }
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, Context};
+use matrix_sdk::attachment::AttachmentConfig;
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+
+use crate::bot::parser::Cmd;
+use crate::db::models::FrontStats;
+use crate::db::queries;
+
+use super::history::format_duration;
+use super::ErrList;
+
+const SWITCH_HOURS: i64 = 3;
+const LONGEST_FRONTS: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    fn parse(word: &str) -> anyhow::Result<Self> {
+        match word {
+            "day" | "d" => Ok(Period::Day),
+            "week" | "w" => Ok(Period::Week),
+            "month" | "m" => Ok(Period::Month),
+            s => bail!("Unknown period `{s}`, must be `day`, `week`, or `month`"),
+        }
+    }
+
+    fn days(&self) -> i32 {
+        match self {
+            Period::Day => 1,
+            Period::Week => 7,
+            Period::Month => 30,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+        }
+    }
+}
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let mut period = Period::Week;
+    let mut csv = false;
+    while let Some(word) = cmd.pop_word() {
+        match word.to_lowercase().as_str() {
+            "csv" => csv = true,
+            word => period = Period::parse(word)?,
+        }
+    }
+    let user = event.sender.as_str();
+    let days = period.days();
+    let stats = queries::get_front_stats(user, days)
+        .await
+        .context("Error getting front stats")?;
+    if stats.is_empty() {
+        room.send(
+            RoomMessageEventContent::text_markdown(format!(
+                "No fronting recorded in the last {}",
+                period.name()
+            )),
+            None,
+        )
+        .await
+        .context("Error sending reply")?;
+        return Ok(vec![]);
+    }
+    let hours = queries::get_switch_hours(user, days, SWITCH_HOURS)
+        .await
+        .context("Error getting switch times")?;
+    let longest = queries::get_longest_fronts(user, days, LONGEST_FRONTS)
+        .await
+        .context("Error getting longest fronts")?;
+
+    let period_secs = i64::from(days) * 86400;
+    let mut msg = format!("#### Front Time in the Last {}\n\n", period.name());
+    msg += "| Member | Time fronted | Share | Fronts | Longest front |\n";
+    msg += "|---|---|---|---|---|\n";
+    for member in &stats {
+        msg += &format!(
+            "| {} | {} | {}% | {} | {} |\n",
+            member.member.replace('|', "\\|"),
+            format_duration(member.seconds),
+            member.seconds * 100 / period_secs,
+            member.fronts,
+            format_duration(member.longest),
+        );
+    }
+    if !hours.is_empty() {
+        msg += "\n#### Most Frequent Switch Times\n\n";
+        for (hour, switches) in hours {
+            msg += &format!("- {hour:02}:00 - {hour:02}:59 UTC: {switches} switches\n");
+        }
+    }
+    if !longest.is_empty() {
+        msg += "\n#### Longest Fronts\n\n";
+        for switch in longest {
+            msg += &format!(
+                "- **{}** from {} for {}\n",
+                switch.members.join("**, **"),
+                switch.started_at,
+                format_duration(switch.duration)
+            );
+        }
+    }
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+
+    if csv {
+        room.send_attachment(
+            &format!("front-stats-{}.csv", period.name()),
+            &mime::TEXT_CSV,
+            stats_csv(&stats).into_bytes(),
+            AttachmentConfig::new(),
+        )
+        .await
+        .context("Error sending CSV file")?;
+    }
+    Ok(vec![])
+}
+
+fn stats_csv(stats: &[FrontStats]) -> String {
+    let mut csv = "member,seconds_fronted,fronts,longest_front_seconds\n".to_owned();
+    for member in stats {
+        csv += &format!(
+            "{},{},{},{}\n",
+            csv_field(&member.member),
+            member.seconds,
+            member.fronts,
+            member.longest
+        );
+    }
+    csv
+}
+
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(csv_field("sasha"), "sasha");
+        assert_eq!(csv_field("sasha, ursa"), "\"sasha, ursa\"");
+        assert_eq!(csv_field("\"kitty\""), "\"\"\"kitty\"\"\"");
+    }
+}
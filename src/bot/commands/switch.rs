@@ -0,0 +1,169 @@
+use anyhow::{bail, Context};
+use matrix_sdk::room::Joined;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::{OwnedUserId, UserId};
+
+use crate::bot::parser::Cmd;
+use crate::db::models::Activated;
+use crate::db::queries;
+use crate::homeserver::HOMESERVER;
+use crate::proxy::{identity, rollout};
+
+use super::{clear, ErrList};
+
+/// `!switch out` clears the current fronter, so no member can be called this.
+pub const SWITCH_OUT: &str = "out";
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let mut names = vec![];
+    while let Some(name) = cmd.pop_quoted_string() {
+        names.push(name);
+    }
+    if let [out] = names.as_slice() {
+        if out == SWITCH_OUT {
+            return clear::exec(room, event).await;
+        }
+    }
+    switch_to(names, room, &event.sender).await
+}
+
+/// Switches in the members and groups matched by activators.
+pub async fn activate(
+    activated: Vec<Activated>,
+    room: &Joined,
+    user: &UserId,
+) -> anyhow::Result<ErrList> {
+    switch_to(activated_names(activated)?, room, user).await
+}
+
+/// The members to switch in for activators, in order. Groups without members are reported
+/// instead of being skipped.
+fn activated_names(activated: Vec<Activated>) -> anyhow::Result<Vec<String>> {
+    let mut names = vec![];
+    for activated in activated {
+        match activated {
+            Activated::Member(name) => names.push(name),
+            Activated::Group { name, members } if members.is_empty() => {
+                bail!(
+                    "Group {name} has no members.\n\nAdd some with `!group {name} add [member...]`"
+                )
+            }
+            Activated::Group { members, .. } => names.extend(members),
+        }
+    }
+    Ok(names)
+}
+
+/// Switches `names` in as the current fronters, in order. Both `!switch` and activators go
+/// through here so they check members the same way.
+pub async fn switch_to(
+    names: Vec<String>,
+    room: &Joined,
+    user: &UserId,
+) -> anyhow::Result<ErrList> {
+    let mut fronters: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        if !fronters.contains(&name) {
+            fronters.push(name);
+        }
+    }
+    if fronters.is_empty() {
+        bail!("Please give the members to switch in, e.g. `!switch [member...]`, or `!switch out` to clear the current fronter");
+    }
+    let mut members = Vec::with_capacity(fronters.len());
+    for name in &fronters {
+        let Some(member) = queries::find_member(user.as_str(), name).await? else {
+            bail!("Member {name} does not exist.\n\nCreate this member with `!m new {name}`");
+        };
+        members.push(member);
+    }
+    queries::set_fronters(user.as_str(), &fronters)
+        .await
+        .context("Error updating current members")?;
+    let identity = identity::compose(user.as_str(), members).await?;
+    let mut msg = fronters_msg(&fronters);
+    match identity.display_name {
+        Some(display_name) => msg += &format!("\n\nShowing as `{display_name}`"),
+        None => msg += "\n\nNo display name set, rooms will keep their current name",
+    }
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    spawn_rollout(room.clone(), user.to_owned());
+    Ok(vec![])
+}
+
+fn fronters_msg(names: &[String]) -> String {
+    match names {
+        [name] => format!("Current fronter set to **{name}**"),
+        names => format!("Current fronters set to **{}**", names.join("**, **")),
+    }
+}
+
+/// Pushes the new identity to the user's recently active rooms in the background and lets them
+/// know about any rooms that couldn't be updated.
+pub fn spawn_rollout(room: Joined, user: OwnedUserId) {
+    tokio::spawn(async move {
+        let msg = match rollout::rollout(user.as_str()).await {
+            Ok(errs) if errs.is_empty() => return,
+            Ok(errs) => {
+                let mut msg =
+                    "#### Couldn't update your name and avatar in some rooms\n\n".to_owned();
+                for (room_id, e) in errs {
+                    tracing::error!("Error rolling out identity for {user} to {room_id}: {e:#}");
+                    let room_name = HOMESERVER
+                        .room_alias(&room_id)
+                        .await
+                        .unwrap_or_else(|_| room_id.to_owned());
+                    msg += &format!("- {room_name}: {e}\n");
+                }
+                msg
+            }
+            Err(e) => {
+                tracing::error!("Error rolling out identity for {user}: {e:#}");
+                format!("Error updating your name and avatar in recent rooms: {e}")
+            }
+        };
+        if let Err(e) = room
+            .send(RoomMessageEventContent::text_markdown(msg), None)
+            .await
+        {
+            tracing::error!("Error sending rollout report: {e}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::activated_names;
+    use crate::db::models::Activated;
+
+    #[test]
+    fn groups_stand_in_for_their_members() {
+        let names = activated_names(vec![
+            Activated::Member("sasha".to_owned()),
+            Activated::Group {
+                name: "cats".to_owned(),
+                members: vec!["kit".to_owned(), "kat".to_owned()],
+            },
+        ])
+        .unwrap();
+        assert_eq!(names, ["sasha", "kit", "kat"]);
+
+        let empty = Activated::Group {
+            name: "dogs".to_owned(),
+            members: vec![],
+        };
+        let err = activated_names(vec![empty]).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Group dogs has no members"),
+            "{err}"
+        );
+    }
+}
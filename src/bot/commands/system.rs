@@ -1,17 +1,186 @@
-use anyhow::Context;
+use anyhow::{anyhow, bail, Context};
 use matrix_sdk::{
     room::Joined,
-    ruma::{events::room::message::RoomMessageEventContent, UserId},
+    ruma::{
+        events::room::message::{OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+        UserId,
+    },
 };
 
+use crate::bot::parser::Cmd;
+use crate::db::models::Member;
 use crate::db::queries;
+use crate::homeserver::HOMESERVER;
 
+use super::history::format_duration;
+use super::member::replied_image;
 use super::ErrList;
 
-pub async fn exec(room: &Joined, user: &UserId) -> anyhow::Result<ErrList> {
-    let members = queries::list_members(user.as_str())
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    if let Some(user) = cmd.pop_user_id() {
+        if user != event.sender {
+            public_system(room, &user).await?;
+            return Ok(vec![]);
+        }
+    }
+    match cmd.pop_word().as_deref() {
+        None => list_system(room, &event.sender, None).await?,
+        Some("set") => set_field(cmd, room, event).await?,
+        Some(group) => {
+            if !queries::group_exists(event.sender.as_str(), group).await? {
+                bail!("Unkown command or group {group}");
+            }
+            list_system(room, &event.sender, Some(group)).await?
+        }
+    }
+    Ok(vec![])
+}
+
+async fn set_field(
+    mut cmd: Cmd,
+    room: &Joined,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<()> {
+    let user = event.sender.as_str();
+    let field = cmd.pop_word().ok_or_else(|| {
+        anyhow!(
+            "Please specify a field, `name`, `tag`, `description`, `avatar`, `pronouns`, or `template`"
+        )
+    })?;
+    queries::create_user(user).await?;
+    if field == "avatar" || field == "av" {
+        let avatar = match cmd.pop_word() {
+            Some(word) if word == "!clear" => None,
+            Some(word) if word == "!acc" => {
+                let profile = HOMESERVER.profile(user).await?;
+                Some(profile.avatar).filter(|avatar| !avatar.is_empty())
+            }
+            Some(word) if word.starts_with("mxc://") => Some(word),
+            Some(word) => {
+                bail!("Unkown argument `{word}`, must be `!clear`, `!acc`, or an mxc url")
+            }
+            None => match replied_image(room, event).await? {
+                Some(mxc) => Some(mxc.to_string()),
+                None => bail!("`!system set avatar` must be sent in reply to an image"),
+            },
+        };
+        queries::set_system_avatar(user, avatar.as_deref()).await?;
+        room.send(
+            RoomMessageEventContent::text_plain("Updated system avatar"),
+            None,
+        )
+        .await
+        .context("Error sending reply")?;
+        return Ok(());
+    }
+    let value = cmd.into_string();
+    if value.is_empty() {
+        bail!("Please give a value for the system {field}, or `!clear` to clear it");
+    }
+    let value = (value != "!clear").then_some(value);
+    match field.as_str() {
+        "name" => queries::set_system_name(user, value.as_deref()).await?,
+        "tag" => queries::set_system_tag(user, value.as_deref()).await?,
+        "description" | "desc" => queries::set_system_description(user, value.as_deref()).await?,
+        "pronouns" => queries::set_system_pronouns(user, value.as_deref()).await?,
+        "template" => {
+            if value.as_ref().is_some_and(|t| !t.contains("{name}")) {
+                bail!("Display name templates must contain `{{name}}`, e.g. `{{name}} ({{pronouns}}) | {{tag}}`");
+            }
+            queries::set_display_name_template(user, value.as_deref()).await?
+        }
+        s => bail!(
+            "Unknown field `{s}`, must be `name`, `tag`, `description`, `avatar`, `pronouns`, or `template`"
+        ),
+    }
+    let msg = match value {
+        Some(value) => format!("Set system {field} to `{value}`"),
+        None => format!("Cleared system {field}"),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+/// Shows what other users are allowed to see of someone's system.
+async fn public_system(room: &Joined, user: &UserId) -> anyhow::Result<()> {
+    if !queries::user_exists(user.as_str()).await? {
+        bail!("{user} doesn't have a system");
+    }
+    let mut members = vec![];
+    for name in queries::list_members(user.as_str())
+        .await
+        .context("Error getting members from user")?
+    {
+        let member = queries::get_member(user.as_str(), &name)
+            .await
+            .with_context(|| format!("Error getting info for member {name}"))?;
+        members.extend(member.public());
+    }
+    let system = queries::get_system(user.as_str())
         .await
-        .context("Error getting members from user")?;
+        .context("Error getting system profile")?;
+    let mut msg = match &system.name {
+        Some(name) => format!("### {name} ({user})"),
+        None => format!("### {user}"),
+    };
+    if let Some(tag) = &system.tag {
+        msg += &format!(" `{tag}`");
+    }
+    msg += "\n\n";
+    if let Some(pronouns) = &system.pronouns {
+        msg += &format!("*{pronouns}*\n\n");
+    }
+    if let Some(description) = &system.description {
+        msg += &format!("{description}\n\n");
+    }
+    if members.is_empty() {
+        msg += "No public members";
+    } else {
+        msg += "#### Members\n\n";
+        for member in members {
+            msg += &format!("- {}\n", member_card(&member));
+        }
+    }
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(())
+}
+
+/// A member's name, pronouns and description on one line, as shown to other users.
+pub fn member_card(member: &Member) -> String {
+    let mut card = format!(
+        "**{}**",
+        member.display_name.as_deref().unwrap_or(&member.name)
+    );
+    if let Some(pronouns) = &member.pronouns {
+        card += &format!(" *{pronouns}*");
+    }
+    if let Some(description) = &member.description {
+        card += &format!(": {description}");
+    }
+    card
+}
+
+/// Lists the system, only showing the members of `group` if one is given.
+async fn list_system(room: &Joined, user: &UserId, group: Option<&str>) -> anyhow::Result<()> {
+    let members = match group {
+        Some(group) => queries::get_group_members(user.as_str(), group)
+            .await
+            .context("Error getting group members")?,
+        None => queries::list_members(user.as_str())
+            .await
+            .context("Error getting members from user")?,
+    };
+    if let (Some(group), true) = (group, members.is_empty()) {
+        bail!("Group {group} has no members yet. Add some using `!group {group} add [name...]`");
+    }
     if members.is_empty() {
         room.send(
             RoomMessageEventContent::text_markdown(
@@ -21,9 +190,31 @@ pub async fn exec(room: &Joined, user: &UserId) -> anyhow::Result<ErrList> {
         )
         .await
         .context("Error sending reply")?;
-        return Ok(vec![]);
+        return Ok(());
+    }
+    let system = queries::get_system(user.as_str())
+        .await
+        .context("Error getting system profile")?;
+    let mut msg = String::new();
+    if let Some(name) = &system.name {
+        msg += &format!("### {name}");
+        if let Some(tag) = &system.tag {
+            msg += &format!(" `{tag}`");
+        }
+        msg += "\n\n";
+    } else if let Some(tag) = &system.tag {
+        msg += &format!("Tag: `{tag}`\n\n");
+    }
+    if let Some(pronouns) = &system.pronouns {
+        msg += &format!("*{pronouns}*\n\n");
+    }
+    if let Some(description) = &system.description {
+        msg += &format!("{description}\n\n");
+    }
+    match group {
+        Some(group) => msg += &format!("#### Members of {group}\n\n"),
+        None => msg += "#### System Members\n\n",
     }
-    let mut msg = "#### System Members\n\n".to_owned();
     for member in members {
         let info = queries::get_member(user.as_str(), &member)
             .await
@@ -34,16 +225,54 @@ pub async fn exec(room: &Joined, user: &UserId) -> anyhow::Result<ErrList> {
         }
         msg += "\n";
     }
-    let current_fronter = queries::get_current_fronter(user.as_str())
+    if group.is_none() {
+        let groups = queries::list_groups(user.as_str())
+            .await
+            .context("Error getting groups")?;
+        if !groups.is_empty() {
+            msg += &format!("\n**Groups:** {}\n", groups.join(", "));
+        }
+    }
+    let fronters = queries::get_current_fronters(user.as_str())
         .await
-        .with_context(|| format!("Error getting current fronter for {user}"))?;
-    if let Some(current_fronter) = current_fronter {
-        msg += &format!("\n**Current fronter: {}**", current_fronter.name);
-    } else {
-        msg += "\n**No current fronter**";
+        .with_context(|| format!("Error getting current fronters for {user}"))?;
+    match fronters.as_slice() {
+        [] => msg += "\n**No current fronter**",
+        [fronter] => msg += &format!("\n**Current fronter: {}**", fronter.name),
+        fronters => {
+            let names: Vec<&str> = fronters.iter().map(|m| m.name.as_str()).collect();
+            msg += &format!("\n**Current fronters: {}**", names.join(", "));
+        }
     }
+    if !fronters.is_empty() {
+        let current_switch = queries::get_switches(user.as_str(), 1)
+            .await
+            .context("Error getting current switch")?;
+        if let Some(switch) = current_switch.first() {
+            msg += &format!(
+                "\n\nFronting since {} ({})",
+                switch.started_at,
+                format_duration(switch.duration)
+            );
+        }
+    }
+    let room_fronters = queries::list_room_fronters(user.as_str())
+        .await
+        .context("Error getting room fronters")?;
+    if !room_fronters.is_empty() {
+        msg += "\n\n#### Room Fronters\n\n";
+        for (room_id, member) in room_fronters {
+            let room_name = HOMESERVER
+                .room_alias(&room_id)
+                .await
+                .unwrap_or_else(|_| room_id.to_owned());
+            msg += &format!("- {room_name}: {member}\n");
+        }
+    }
+    let autoproxy = queries::get_autoproxy(user.as_str()).await?;
+    msg += &format!("\n\nAutoproxy: `{autoproxy}`");
     room.send(RoomMessageEventContent::text_markdown(msg), None)
         .await
         .context("Error sending reply")?;
-    Ok(vec![])
+    Ok(())
 }
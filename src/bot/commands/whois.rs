@@ -0,0 +1,238 @@
+//! Answers who sent a proxied message, either with `!whois` in the DM or by reacting to the
+//! message with ❓ in a room the bot is in.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use matrix_sdk::room::{Joined, Room};
+use matrix_sdk::ruma::api::client::room::create_room;
+use matrix_sdk::ruma::api::client::room::Visibility;
+use matrix_sdk::ruma::events::reaction::OriginalSyncReactionEvent;
+use matrix_sdk::ruma::events::room::message::{
+    OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+};
+use matrix_sdk::ruma::{EventId, UserId};
+use matrix_sdk::{Client, RoomMemberships};
+use once_cell::sync::Lazy;
+
+use crate::bot::parser::Cmd;
+use crate::db::models::SentMessage;
+use crate::db::queries;
+use crate::homeserver::HOMESERVER;
+
+use super::system::member_card;
+use super::ErrList;
+
+const NO_RECORD: &str = "Plural Kitty has no record of who sent this message";
+const WHOIS_REACTION: &str = "❓";
+/// How many ❓ lookups a user can make in [`LOOKUP_WINDOW`], each one sends them a DM.
+const LOOKUP_LIMIT: usize = 5;
+const LOOKUP_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+static LOOKUPS: Lazy<Mutex<RateLimiter>> =
+    Lazy::new(|| Mutex::new(RateLimiter::new(LOOKUP_LIMIT, LOOKUP_WINDOW)));
+
+pub async fn exec(
+    mut cmd: Cmd,
+    room: &Joined,
+    client: &Client,
+    event: &OriginalSyncRoomMessageEvent,
+) -> anyhow::Result<ErrList> {
+    let (room_id, event_id) = cmd.pop_event_id(client).await?.ok_or_else(|| {
+        anyhow!(
+            "Please give a link to the message, e.g. from its \"Share\" or \"Copy link\" option"
+        )
+    })?;
+    let sent = queries::get_sent_message(event_id.as_str())
+        .await
+        .context("Error looking up message")?
+        .filter(|sent| room_id.map_or(true, |room_id| room_id.as_str() == sent.room_id));
+    let msg = match sent {
+        Some(sent) if can_see(&sent, event.sender.as_str()).await? => {
+            describe(&sent, event.sender.as_str()).await?
+        }
+        _ => NO_RECORD.to_owned(),
+    };
+    room.send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending reply")?;
+    Ok(vec![])
+}
+
+/// Only people in the room a message was sent in can find out who sent it.
+pub async fn can_see(sent: &SentMessage, asker: &str) -> anyhow::Result<bool> {
+    if sent.mxid == asker {
+        return Ok(true);
+    }
+    HOMESERVER.is_room_member(&sent.room_id, asker).await
+}
+
+/// Describes the members a message was sent as, and the account they belong to. Only the account
+/// itself sees private members and fields.
+pub async fn describe(sent: &SentMessage, asker: &str) -> anyhow::Result<String> {
+    let system = queries::get_system(&sent.mxid).await?;
+    let mut msg = match &system.name {
+        Some(name) => format!("#### Sent by {name} ({})\n\n", sent.mxid),
+        None => format!("#### Sent by {}\n\n", sent.mxid),
+    };
+    for name in &sent.members {
+        let member = queries::get_member(&sent.mxid, name).await?;
+        let member = if asker == sent.mxid {
+            member
+        } else {
+            match member.public() {
+                Some(member) => member,
+                None => {
+                    msg += "- *A private member*\n";
+                    continue;
+                }
+            }
+        };
+        msg += &format!("- {}\n", member_card(&member));
+    }
+    Ok(msg)
+}
+
+/// DMs the member card of whoever sent a proxied message to people who react to it with ❓.
+pub async fn reaction_handler(event: OriginalSyncReactionEvent, client: Client, room: Room) {
+    let Room::Joined(room) = room else {
+        return;
+    };
+    if client.user_id() == Some(&*event.sender) {
+        return;
+    }
+    let annotation = &event.content.relates_to;
+    // Some clients add a variation selector to emoji
+    if annotation.key.trim_end_matches('\u{fe0f}') != WHOIS_REACTION {
+        return;
+    }
+    if let Err(e) = answer_reaction(&client, &room, &event.sender, &annotation.event_id).await {
+        tracing::error!(
+            "Error answering {WHOIS_REACTION} from {} on {}: {e:#}",
+            event.sender,
+            annotation.event_id
+        );
+    }
+}
+
+async fn answer_reaction(
+    client: &Client,
+    room: &Joined,
+    user: &UserId,
+    event_id: &EventId,
+) -> anyhow::Result<()> {
+    let Some(sent) = queries::get_sent_message(event_id.as_str())
+        .await?
+        .filter(|sent| sent.room_id == room.room_id().as_str())
+    else {
+        return Ok(());
+    };
+    if !LOOKUPS.lock().unwrap().check(user.as_str(), Instant::now()) {
+        tracing::debug!("Rate limiting {WHOIS_REACTION} lookups from {user}");
+        return Ok(());
+    }
+    let msg = format!(
+        "{}\nFor [this message](https://matrix.to/#/{}/{event_id})",
+        describe(&sent, user.as_str()).await?,
+        room.room_id()
+    );
+    dm_room(client, user)
+        .await?
+        .send(RoomMessageEventContent::text_markdown(msg), None)
+        .await
+        .context("Error sending DM")?;
+    Ok(())
+}
+
+/// Finds the bot's DM with `user`, creating one if there isn't one yet. Like the command handler,
+/// any room with only the bot and the user in it counts, even if the user hasn't accepted the bot's
+/// invite yet. A room the user invited the bot to is joined right away instead of waiting for the
+/// auto-join.
+async fn dm_room(client: &Client, user: &UserId) -> anyhow::Result<Joined> {
+    if let Some(room) = client.get_dm_room(user) {
+        return Ok(room);
+    }
+    for room in client.joined_rooms() {
+        let members = room
+            .members(RoomMemberships::JOIN | RoomMemberships::INVITE)
+            .await?;
+        if members.len() == 2 && members.iter().any(|member| member.user_id() == user) {
+            return Ok(room);
+        }
+    }
+    for room in client.invited_rooms() {
+        let inviter = room.invite_details().await?.inviter;
+        if inviter.is_some_and(|inviter| inviter.user_id() == user) {
+            return client
+                .join_room_by_id(room.room_id())
+                .await
+                .with_context(|| format!("Error joining DM with {user}"));
+        }
+    }
+    let mut request = create_room::v3::Request::new();
+    request.invite = vec![user.to_owned()];
+    request.is_direct = true;
+    request.preset = Some(create_room::v3::RoomPreset::TrustedPrivateChat);
+    request.visibility = Visibility::Private;
+    client
+        .create_room(request)
+        .await
+        .with_context(|| format!("Error creating DM with {user}"))
+}
+
+/// Limits how often each user can do something within a sliding window.
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    hits: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        RateLimiter {
+            limit,
+            window,
+            hits: HashMap::new(),
+        }
+    }
+
+    /// Records an attempt by `key` at `now`, returns `false` if it is over the limit.
+    pub fn check(&mut self, key: &str, now: Instant) -> bool {
+        let window = self.window;
+        let expired = |hit: &Instant| now.duration_since(*hit) >= window;
+        self.hits
+            .retain(|_, hits| !hits.back().is_some_and(expired));
+        let hits = self.hits.entry(key.to_owned()).or_default();
+        while hits.front().is_some_and(expired) {
+            hits.pop_front();
+        }
+        if hits.len() >= self.limit {
+            return false;
+        }
+        hits.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn rate_limit() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(limiter.check("@a:test.local", start));
+        assert!(limiter.check("@a:test.local", start + Duration::from_secs(10)));
+        assert!(!limiter.check("@a:test.local", start + Duration::from_secs(20)));
+        assert!(limiter.check("@b:test.local", start + Duration::from_secs(20)));
+        // The first attempt has left the window
+        assert!(limiter.check("@a:test.local", start + Duration::from_secs(60)));
+        assert!(!limiter.check("@a:test.local", start + Duration::from_secs(61)));
+        assert!(limiter.check("@a:test.local", start + Duration::from_secs(200)));
+    }
+}
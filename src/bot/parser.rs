@@ -221,6 +221,29 @@ impl Cmd {
         }
     }
 
+    /// Pops an event link, or a bare event ID in which case the room isn't known.
+    pub async fn pop_event_id(
+        &mut self,
+        client: &Client,
+    ) -> anyhow::Result<Option<(Option<OwnedRoomId>, OwnedEventId)>> {
+        match get!(self) {
+            Some(CmdPart::EventId(room, event_id)) => {
+                let event_id = event_id.to_owned();
+                let room_id = room.as_room_id(client).await?;
+                self.pointer += 1;
+                Ok(Some((Some(room_id), event_id)))
+            }
+            Some(CmdPart::Word(word)) if word.starts_with('$') => match EventId::parse(word) {
+                Ok(event_id) => {
+                    self.pointer += 1;
+                    Ok(Some((None, event_id)))
+                }
+                Err(_) => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
     pub fn pop_room_alias(&mut self) -> Option<OwnedRoomAliasId> {
         match get!(self) {
             Some(CmdPart::RoomAlias(alias)) => ret!(self, alias.to_owned()),
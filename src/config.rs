@@ -9,11 +9,30 @@ use once_cell::sync::Lazy;
 use serde::Deserialize;
 use sqlx::postgres::PgConnectOptions;
 
+use crate::db::models::FailPolicy;
+
 #[derive(Deserialize)]
 pub struct Config {
     pub listen: SocketAddr,
     pub synapse: SynapseInfo,
     pub bot: BotInfo,
+    /// Turns on syncing with PluralKit when set
+    pub pluralkit: Option<PluralKitInfo>,
+    /// Resolves access tokens with OAuth2 token introspection when set
+    pub oidc: Option<OidcInfo>,
+    /// Registration files of appservices whose users can use Plural Kitty
+    #[serde(default)]
+    pub appservices: Vec<PathBuf>,
+    /// What happens to messages from users who haven't chosen a fail policy
+    #[serde(default)]
+    pub fail_policy: FailPolicy,
+    /// How many more times the `retry` fail policy tries to update the sender's identity
+    #[serde(default = "default_fail_retries")]
+    pub fail_retries: u32,
+}
+
+fn default_fail_retries() -> u32 {
+    3
 }
 
 #[derive(Deserialize)]
@@ -26,6 +45,21 @@ pub struct BotInfo {
     pub db: DbInfo,
     pub display_name: Option<String>,
     pub avatar: Option<OwnedMxcUri>,
+    /// How many of a user's most recently active rooms get their new identity right after a
+    /// switch. `0` turns this off.
+    #[serde(default = "default_rollout_rooms")]
+    pub rollout_rooms: i64,
+    /// Milliseconds to wait between rooms during a rollout.
+    #[serde(default = "default_rollout_delay")]
+    pub rollout_delay_ms: u64,
+}
+
+fn default_rollout_rooms() -> i64 {
+    10
+}
+
+fn default_rollout_delay() -> u64 {
+    500
 }
 
 impl BotInfo {
@@ -44,10 +78,77 @@ impl BotInfo {
     }
 }
 
+#[derive(Deserialize)]
+pub struct PluralKitInfo {
+    /// Base URL of a PluralKit v2 compatible API, without a trailing `/`
+    #[serde(default = "default_pluralkit_api_url")]
+    pub api_url: String,
+    /// Seconds between syncs of every linked system.
+    #[serde(default = "default_sync_interval")]
+    pub sync_interval_secs: u64,
+}
+
+fn default_pluralkit_api_url() -> String {
+    "https://api.pluralkit.me/v2".to_owned()
+}
+
+fn default_sync_interval() -> u64 {
+    15 * 60
+}
+
+/// The OIDC provider the homeserver delegates auth to, e.g. Matrix Authentication Service
+/// (MSC3861). Its tokens aren't in Synapse's database so they are checked with the provider.
+#[derive(Deserialize)]
+pub struct OidcInfo {
+    /// e.g. `https://auth.example.com/oauth2/introspect`
+    pub introspection_endpoint: String,
+    pub client_id: String,
+    client_secret: Option<String>,
+    client_secret_file: Option<PathBuf>,
+}
+
+impl OidcInfo {
+    pub async fn client_secret(&self) -> anyhow::Result<Option<String>> {
+        match (&self.client_secret, &self.client_secret_file) {
+            (Some(secret), _) => Ok(Some(secret.to_owned())),
+            (None, Some(path)) => {
+                let secret = tokio::fs::read_to_string(path)
+                    .await
+                    .context("Error reading client_secret_file")?;
+                Ok(Some(secret.trim().to_owned()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct SynapseInfo {
     pub host: String,
-    pub db: DbInfo,
+    /// Synapse's own database, only needed by the `synapse_db` backend
+    pub db: Option<DbInfo>,
+    /// Defaults to `synapse_db` when `db` is set and `client_server` otherwise
+    backend: Option<HomeserverBackend>,
+}
+
+/// How Plural Kitty looks up users, profiles, and rooms on the homeserver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HomeserverBackend {
+    /// Read Synapse's database directly
+    SynapseDb,
+    /// Only use the Matrix Client-Server API, works with any homeserver
+    ClientServer,
+}
+
+impl SynapseInfo {
+    pub fn backend(&self) -> HomeserverBackend {
+        match (self.backend, &self.db) {
+            (Some(backend), _) => backend,
+            (None, Some(_)) => HomeserverBackend::SynapseDb,
+            (None, None) => HomeserverBackend::ClientServer,
+        }
+    }
 }
 
 #[derive(Deserialize)]
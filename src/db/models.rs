@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Member {
     pub mxid: String,
     pub name: String,
@@ -5,6 +6,83 @@ pub struct Member {
     pub avatar: Option<String>,
     pub activators: Vec<String>,
     pub track_account: bool,
+    pub proxy_tags: Vec<String>,
+    pub pronouns: Option<String>,
+    pub description: Option<String>,
+    /// Hex colour without the leading `#`, e.g. `ff8800`
+    pub color: Option<String>,
+    /// `YYYY-MM-DD`, or `MM-DD` if the year isn't known
+    pub birthday: Option<String>,
+    pub notes: Option<String>,
+    /// Hidden from other users entirely
+    pub private: bool,
+    /// Fields hidden from other users, from [`PRIVACY_FIELDS`]
+    pub private_fields: Vec<String>,
+}
+
+/// Member fields that can be hidden from other users.
+pub const PRIVACY_FIELDS: &[&str] = &[
+    "displayname",
+    "avatar",
+    "pronouns",
+    "description",
+    "color",
+    "birthday",
+];
+
+impl Member {
+    /// A member with nothing but a name.
+    pub fn new(mxid: &str, name: &str) -> Self {
+        Member {
+            mxid: mxid.to_owned(),
+            name: name.to_owned(),
+            display_name: None,
+            avatar: None,
+            activators: vec![],
+            track_account: false,
+            proxy_tags: vec![],
+            pronouns: None,
+            description: None,
+            color: None,
+            birthday: None,
+            notes: None,
+            private: false,
+            private_fields: vec![],
+        }
+    }
+
+    /// The member as other users see them, or `None` if the member is private. Private fields
+    /// are cleared, as are notes, activators, and proxy tags which are never shown to others.
+    pub fn public(mut self) -> Option<Member> {
+        if self.private {
+            return None;
+        }
+        let private_fields = std::mem::take(&mut self.private_fields);
+        let hidden = |field: &str| private_fields.iter().any(|f| f == field);
+        if hidden("displayname") {
+            self.display_name = None;
+        }
+        if hidden("avatar") {
+            self.avatar = None;
+        }
+        if hidden("pronouns") {
+            self.pronouns = None;
+        }
+        if hidden("description") {
+            self.description = None;
+        }
+        if hidden("color") {
+            self.color = None;
+        }
+        if hidden("birthday") {
+            self.birthday = None;
+        }
+        self.notes = None;
+        self.activators.clear();
+        self.proxy_tags.clear();
+        self.track_account = false;
+        Some(self)
+    }
 }
 
 #[derive(sqlx::FromRow)]
@@ -14,3 +92,241 @@ pub struct ProfileInfo {
     #[sqlx(rename = "avatar_url")]
     pub avatar: String,
 }
+
+/// Decides which member the proxy uses for messages that don't have a proxy tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoproxyMode {
+    /// Use the current fronter
+    Front,
+    /// Use the last member selected by a proxy tag or activator
+    Latch,
+    /// Only proxy messages with a proxy tag, other messages use the account's own profile
+    Off,
+}
+
+impl AutoproxyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AutoproxyMode::Front => "front",
+            AutoproxyMode::Latch => "latch",
+            AutoproxyMode::Off => "off",
+        }
+    }
+}
+
+impl std::str::FromStr for AutoproxyMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "front" => Ok(AutoproxyMode::Front),
+            "latch" => Ok(AutoproxyMode::Latch),
+            "off" => Ok(AutoproxyMode::Off),
+            s => Err(anyhow::anyhow!(
+                "Unknown autoproxy mode `{s}`, must be `front`, `latch`, or `off`"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for AutoproxyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What the proxy does with a message when it can't give the sender the right identity first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailPolicy {
+    /// Send the message anyway, it may show the wrong member
+    #[default]
+    FailOpen,
+    /// Don't send the message
+    FailClosed,
+    /// Try again a few times, then don't send the message
+    Retry,
+}
+
+impl FailPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailPolicy::FailOpen => "fail-open",
+            FailPolicy::FailClosed => "fail-closed",
+            FailPolicy::Retry => "retry",
+        }
+    }
+}
+
+impl std::str::FromStr for FailPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail-open" | "open" => Ok(FailPolicy::FailOpen),
+            "fail-closed" | "closed" => Ok(FailPolicy::FailClosed),
+            "retry" => Ok(FailPolicy::Retry),
+            s => Err(anyhow::anyhow!(
+                "Unknown fail policy `{s}`, must be `fail-open`, `fail-closed`, or `retry`"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for FailPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which avatar is used while more than one member is fronting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CofrontAvatar {
+    /// The avatar of the first fronter that has one
+    First,
+    /// The account's own avatar
+    Account,
+}
+
+impl CofrontAvatar {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CofrontAvatar::First => "first",
+            CofrontAvatar::Account => "account",
+        }
+    }
+}
+
+impl std::str::FromStr for CofrontAvatar {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(CofrontAvatar::First),
+            "account" => Ok(CofrontAvatar::Account),
+            s => Err(anyhow::anyhow!(
+                "Unknown co-fronting avatar rule `{s}`, must be `first` or `account`"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for CofrontAvatar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct CofrontSettings {
+    pub separator: String,
+    pub avatar: CofrontAvatar,
+}
+
+impl Default for CofrontSettings {
+    fn default() -> Self {
+        CofrontSettings {
+            separator: "&".to_owned(),
+            avatar: CofrontAvatar::First,
+        }
+    }
+}
+
+pub struct Switch {
+    pub members: Vec<String>,
+    pub started_at: String,
+    /// Seconds until the next switch, or until now for the current one
+    pub duration: i64,
+}
+
+pub struct FrontStats {
+    pub member: String,
+    /// Seconds fronted inside the period
+    pub seconds: i64,
+    pub fronts: i64,
+    /// Seconds of the member's longest front
+    pub longest: i64,
+}
+
+#[derive(Default)]
+pub struct SystemProfile {
+    pub name: Option<String>,
+    pub tag: Option<String>,
+    pub description: Option<String>,
+    /// Used when a fronting member doesn't have an avatar
+    pub avatar: Option<String>,
+    pub pronouns: Option<String>,
+    /// Display name template, e.g. `{name} {pronouns} | {tag}`
+    pub template: Option<String>,
+}
+
+pub struct Group {
+    pub mxid: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Activators that switch the whole group in
+    pub activators: Vec<String>,
+}
+
+/// What an activator switches in.
+pub enum Activated {
+    Member(String),
+    /// A group's activator, standing in for all of its members
+    Group {
+        name: String,
+        members: Vec<String>,
+    },
+}
+
+/// A system linked to PluralKit with `!pk link`.
+pub struct PkLink {
+    pub mxid: String,
+    pub token: String,
+    /// PluralKit's ID for the system, e.g. `abcde`
+    pub system_id: String,
+    /// RFC 3339, switches started at or before this have been pushed already
+    pub switches_pushed_until: Option<String>,
+    /// RFC 3339
+    pub last_synced: Option<String>,
+}
+
+/// Last-synced marker for a member linked to a PluralKit member.
+pub struct PkMemberSync {
+    pub member: String,
+    pub pk_id: String,
+    /// RFC 3339
+    pub synced_at: String,
+    /// The member's fields on both sides as of `synced_at`, as JSON
+    pub snapshot: String,
+}
+
+/// A message sent through the proxy as one or more members.
+pub struct SentMessage {
+    pub room_id: String,
+    pub mxid: String,
+    /// In front order
+    pub members: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FailPolicy;
+
+    #[test]
+    fn fail_policy_names() {
+        for policy in [
+            FailPolicy::FailOpen,
+            FailPolicy::FailClosed,
+            FailPolicy::Retry,
+        ] {
+            assert_eq!(policy.as_str().parse::<FailPolicy>().unwrap(), policy);
+            // The config file uses the same names as the database and the bot
+            let from_config: FailPolicy = serde_yaml::from_str(policy.as_str()).unwrap();
+            assert_eq!(from_config, policy);
+        }
+        assert_eq!(
+            "closed".parse::<FailPolicy>().unwrap(),
+            FailPolicy::FailClosed
+        );
+        assert!("sometimes".parse::<FailPolicy>().is_err());
+    }
+}
@@ -1,29 +1,7 @@
 use anyhow::{Context, anyhow};
-use sqlx::Row;
 
+use super::PK_POOL;
 use super::{models::*, DbError};
-use super::{PK_POOL, SYNAPSE_POOL};
-
-pub async fn get_synapse_user(access_token: &str) -> anyhow::Result<String> {
-    sqlx::query("SELECT user_id FROM access_tokens WHERE token = $1")
-        .bind(access_token)
-        .map(|row| row.get::<String, usize>(0))
-        .fetch_one(&*SYNAPSE_POOL)
-        .await
-        .context("Error getting user from auth token")
-}
-
-pub async fn get_synapse_profile(mxid: &str) -> anyhow::Result<ProfileInfo> {
-    sqlx::query_as(
-        r#"SELECT 
-        COALESCE(displayname, '') AS displayname, COALESCE(avatar_url, '') AS avatar_url
-        FROM profiles WHERE full_user_id = $1"#,
-    )
-    .bind(mxid)
-    .fetch_one(&*SYNAPSE_POOL)
-    .await
-    .with_context(|| format!("Error getting display name and avatar for {mxid}"))
-}
 
 pub async fn read_msgs(room_id: &str, event_id: &str) -> sqlx::Result<bool> {
     let read = sqlx::query!(
@@ -58,58 +36,219 @@ pub async fn create_user(mxid: &str) -> sqlx::Result<bool> {
     .map(|res| res.rows_affected() > 0)
 }
 
+pub async fn user_exists(mxid: &str) -> sqlx::Result<bool> {
+    sqlx::query!("SELECT 1 AS x FROM users WHERE mxid = $1", mxid)
+        .fetch_optional(&*PK_POOL)
+        .await
+        .map(|res| res.is_some())
+}
+
+pub async fn get_system(mxid: &str) -> sqlx::Result<SystemProfile> {
+    sqlx::query_as!(
+        SystemProfile,
+        r#"
+        SELECT
+            system_name AS name,
+            system_tag AS tag,
+            system_description AS description,
+            system_avatar AS avatar,
+            system_pronouns AS pronouns,
+            display_name_template AS template
+        FROM users
+        WHERE mxid = $1
+        "#,
+        mxid
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .map(Option::unwrap_or_default)
+}
+
+pub async fn set_system_name(mxid: &str, name: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET system_name = $2 WHERE mxid = $1",
+        mxid,
+        name
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_system_tag(mxid: &str, tag: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET system_tag = $2 WHERE mxid = $1",
+        mxid,
+        tag
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_system_description(mxid: &str, description: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET system_description = $2 WHERE mxid = $1",
+        mxid,
+        description
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_system_avatar(mxid: &str, avatar: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET system_avatar = $2 WHERE mxid = $1",
+        mxid,
+        avatar
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_system_pronouns(mxid: &str, pronouns: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET system_pronouns = $2 WHERE mxid = $1",
+        mxid,
+        pronouns
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_display_name_template(mxid: &str, template: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET display_name_template = $2 WHERE mxid = $1",
+        mxid,
+        template
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
 pub async fn get_users() -> sqlx::Result<Vec<String>> {
     sqlx::query_scalar!("SELECT mxid FROM users")
         .fetch_all(&*PK_POOL)
         .await
 }
 
-pub async fn create_member(mxid: &str, name: &str) -> anyhow::Result<()> {
+/// Saves a whole member. If the name is already taken all fields of the existing member are
+/// replaced by those of `member`.
+pub async fn save_member(member: &Member) -> sqlx::Result<()> {
     sqlx::query!(
-        "INSERT INTO members (mxid, name) VALUES ($1, $2);",
-        mxid,
-        name
+        r#"
+        INSERT INTO members (
+            mxid, name, display_name, avatar, activators, track_account, proxy_tags,
+            pronouns, description, color, birthday, notes, private, private_fields
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT (mxid, name) DO UPDATE SET
+            display_name = EXCLUDED.display_name,
+            avatar = EXCLUDED.avatar,
+            activators = EXCLUDED.activators,
+            track_account = EXCLUDED.track_account,
+            proxy_tags = EXCLUDED.proxy_tags,
+            pronouns = EXCLUDED.pronouns,
+            description = EXCLUDED.description,
+            color = EXCLUDED.color,
+            birthday = EXCLUDED.birthday,
+            notes = EXCLUDED.notes,
+            private = EXCLUDED.private,
+            private_fields = EXCLUDED.private_fields
+        "#,
+        member.mxid,
+        member.name,
+        member.display_name,
+        member.avatar,
+        &member.activators,
+        member.track_account,
+        &member.proxy_tags,
+        member.pronouns,
+        member.description,
+        member.color,
+        member.birthday,
+        member.notes,
+        member.private,
+        &member.private_fields,
     )
     .execute(&*PK_POOL)
-    .await
-    .map(|_| ())
-    .map_err(|e| {
-        if e.not_unique() {
-            anyhow!(e).context("This member name is already in use")
-        } else {
-            e.into()
-        }
-    })
+    .await?;
+    Ok(())
 }
 
+/// Deletes a member, recording a switch if they were fronting.
 pub async fn remove_member(mxid: &str, name: &str) -> sqlx::Result<()> {
+    let mut tx = PK_POOL.begin().await?;
+    let was_fronting = sqlx::query!(
+        "DELETE FROM fronters WHERE mxid = $1 AND member = $2;",
+        mxid,
+        name
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected()
+        > 0;
     sqlx::query!(
         "DELETE FROM members WHERE mxid = $1 AND name = $2;",
         mxid,
         name
     )
-    .execute(&*PK_POOL)
+    .execute(&mut tx)
     .await?;
     sqlx::query!(
-        "UPDATE users SET current_fronter = null WHERE mxid = $1 AND current_fronter = $2;",
+        "UPDATE users SET latched_member = null WHERE mxid = $1 AND latched_member = $2;",
         mxid,
         name
     )
-    .execute(&*PK_POOL)
-    .await
-    .map(|_| ())
+    .execute(&mut tx)
+    .await?;
+    if was_fronting {
+        sqlx::query!(
+            r#"
+            INSERT INTO switches (mxid, members)
+            SELECT $1, COALESCE(array_agg(member ORDER BY position), '{}')
+            FROM fronters
+            WHERE mxid = $1
+            "#,
+            mxid
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await
 }
 
 pub async fn rename_member(mxid: &str, old_name: &str, new_name: &str) -> sqlx::Result<()> {
+    let mut tx = PK_POOL.begin().await?;
     sqlx::query!(
         "UPDATE members SET name = $3 WHERE mxid = $1 AND name = $2;",
         mxid,
         old_name,
         new_name,
     )
-    .execute(&*PK_POOL)
-    .await
-    .map(|_| ())
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE users SET latched_member = $3 WHERE mxid = $1 AND latched_member = $2;",
+        mxid,
+        old_name,
+        new_name,
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE switches SET members = array_replace(members, $2, $3) WHERE mxid = $1;",
+        mxid,
+        old_name,
+        new_name,
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await
 }
 
 pub async fn add_display_name(mxid: &str, name: &str, display_name: &str) -> sqlx::Result<()> {
@@ -182,162 +321,1139 @@ pub async fn remove_activator(mxid: &str, name: &str, activator: &str) -> sqlx::
     Ok(())
 }
 
-pub async fn member_exists(mxid: &str, name: &str) -> sqlx::Result<bool> {
+pub async fn set_member_pronouns(
+    mxid: &str,
+    name: &str,
+    pronouns: Option<&str>,
+) -> sqlx::Result<()> {
     sqlx::query!(
-        "SELECT 1 as x FROM members WHERE mxid = $1 AND name = $2;",
+        "UPDATE members SET pronouns = $3 WHERE mxid = $1 AND name = $2",
         mxid,
-        name
+        name,
+        pronouns
     )
-    .fetch_optional(&*PK_POOL)
-    .await
-    .map(|res| res.is_some())
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
 }
 
-pub async fn get_member(mxid: &str, name: &str) -> sqlx::Result<Member> {
-    sqlx::query_as!(
-        Member,
-        "SELECT * FROM members WHERE mxid = $1 AND name = $2",
+pub async fn set_member_description(
+    mxid: &str,
+    name: &str,
+    description: Option<&str>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE members SET description = $3 WHERE mxid = $1 AND name = $2",
         mxid,
-        name
+        name,
+        description
     )
-    .fetch_one(&*PK_POOL)
-    .await
-}
-
-pub async fn list_members(mxid: &str) -> sqlx::Result<Vec<String>> {
-    sqlx::query_scalar!("SELECT name FROM members WHERE mxid = $1;", mxid)
-        .fetch_all(&*PK_POOL)
-        .await
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
 }
 
-pub async fn set_current_fronter(mxid: &str, name: Option<&str>) -> sqlx::Result<()> {
+pub async fn set_member_color(mxid: &str, name: &str, color: Option<&str>) -> sqlx::Result<()> {
     sqlx::query!(
-        "UPDATE users SET current_fronter = $2 WHERE mxid = $1;",
+        "UPDATE members SET color = $3 WHERE mxid = $1 AND name = $2",
         mxid,
-        name
+        name,
+        color
     )
     .execute(&*PK_POOL)
     .await?;
     Ok(())
 }
 
-pub async fn get_current_fronter(mxid: &str) -> anyhow::Result<Option<Member>> {
-    sqlx::query_as!(
-        Member,
-        r#"
-        SELECT
-            m.mxid AS mxid,
-            m.name AS name, 
-            m.display_name AS display_name,
-            m.avatar AS avatar,
-            m.activators AS activators,
-            m.track_account AS track_account
-        FROM users AS u 
-            JOIN members AS m ON u.mxid = m.mxid AND u.current_fronter = m.name
-        WHERE u.mxid = $1
-        "#,
-        mxid
-    )
-    .fetch_optional(&*PK_POOL)
-    .await
-    .context("Error getting current_fronter")
-}
-
-pub async fn set_fronter_from_activator(
+pub async fn set_member_birthday(
     mxid: &str,
-    activator: &str,
-) -> sqlx::Result<Option<String>> {
-    sqlx::query_scalar!(
-        r#"
-        UPDATE users
-        SET current_fronter = sub.name
-        FROM (
-            SELECT name
-            FROM members
-            WHERE mxid = $1 AND $2 = ANY(activators)
-        ) AS sub
-        WHERE mxid = $1
-        RETURNING current_fronter AS "name!"
-    "#,
+    name: &str,
+    birthday: Option<&str>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE members SET birthday = $3 WHERE mxid = $1 AND name = $2",
         mxid,
-        activator
+        name,
+        birthday
     )
-    .fetch_optional(&*PK_POOL)
-    .await
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
 }
 
-pub async fn update_tracking_member(mxid: &str, profile: &ProfileInfo) -> sqlx::Result<()> {
+pub async fn set_member_notes(mxid: &str, name: &str, notes: Option<&str>) -> sqlx::Result<()> {
     sqlx::query!(
-        r#"
-        UPDATE members
-        SET 
-            display_name = $2,
-            avatar = $3
-        WHERE mxid = $1
-        AND track_account = TRUE
-    "#,
+        "UPDATE members SET notes = $3 WHERE mxid = $1 AND name = $2",
         mxid,
-        profile.display_name,
-        profile.avatar
+        name,
+        notes
     )
     .execute(&*PK_POOL)
     .await?;
     Ok(())
 }
 
-pub async fn toggle_tracking(mxid: &str, name: &str) -> sqlx::Result<bool> {
-    sqlx::query_scalar!(
-        "UPDATE members SET track_account = NOT track_account
-        WHERE mxid = $1 AND name = $2 RETURNING track_account",
+pub async fn set_member_private(mxid: &str, name: &str, private: bool) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE members SET private = $3 WHERE mxid = $1 AND name = $2",
         mxid,
-        name
+        name,
+        private
     )
-    .fetch_one(&*PK_POOL)
-    .await
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
 }
 
-pub async fn is_room_ignored(mxid: &str, room_id: &str) -> sqlx::Result<bool> {
+/// Hides or shows one of a member's fields to other users.
+pub async fn set_member_field_private(
+    mxid: &str,
+    name: &str,
+    field: &str,
+    private: bool,
+) -> sqlx::Result<()> {
     sqlx::query!(
-        "SELECT NULL AS x FROM ignored_rooms WHERE mxid = $1 AND room_id = $2",
+        r#"
+        UPDATE members SET private_fields = CASE
+            WHEN $4 THEN ARRAY(SELECT DISTINCT unnest(array_append(private_fields, $3)))
+            ELSE array_remove(private_fields, $3)
+        END
+        WHERE mxid = $1 AND name = $2
+        "#,
         mxid,
-        room_id
+        name,
+        field,
+        private
     )
-    .fetch_optional(&*PK_POOL)
-    .await
-    .map(|res| res.is_some())
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
 }
 
-pub async fn ignore_room(mxid: &str, room_id: &str) -> sqlx::Result<()> {
+pub async fn add_proxy_tag(mxid: &str, name: &str, tag: &str) -> sqlx::Result<()> {
     sqlx::query!(
-        "INSERT INTO ignored_rooms (mxid, room_id) VALUES ($1, $2)",
+        "UPDATE members SET proxy_tags = array_append(proxy_tags, $3) WHERE mxid = $1 AND name = $2",
         mxid,
-        room_id
+        name,
+        tag
     )
     .execute(&*PK_POOL)
     .await?;
     Ok(())
 }
 
-pub async fn unignore_room(mxid: &str, room_id: &str) -> sqlx::Result<()> {
+pub async fn remove_proxy_tag(mxid: &str, name: &str, tag: &str) -> sqlx::Result<()> {
     sqlx::query!(
-        "DELETE FROM ignored_rooms WHERE mxid = $1 AND room_id = $2",
+        "UPDATE members SET proxy_tags = array_remove(proxy_tags, $3) WHERE mxid = $1 AND name = $2",
         mxid,
-        room_id
+        name,
+        tag
     )
     .execute(&*PK_POOL)
     .await?;
     Ok(())
 }
 
-pub async fn list_ignored(mxid: &str) -> sqlx::Result<Vec<String>> {
-    sqlx::query_scalar!("SELECT room_id FROM ignored_rooms WHERE mxid = $1", mxid)
-        .fetch_all(&*PK_POOL)
-        .await
+pub async fn get_tagged_members(mxid: &str) -> sqlx::Result<Vec<Member>> {
+    sqlx::query_as!(
+        Member,
+        "SELECT * FROM members WHERE mxid = $1 AND proxy_tags <> '{}'",
+        mxid
+    )
+    .fetch_all(&*PK_POOL)
+    .await
 }
 
-pub async fn room_alias(room_id: &str) -> sqlx::Result<String> {
-    sqlx::query_scalar("SELECT room_alias FROM room_aliases WHERE room_id = $1")
-        .bind(room_id)
-        .fetch_one(&*SYNAPSE_POOL)
-        .await
+pub async fn member_exists(mxid: &str, name: &str) -> sqlx::Result<bool> {
+    sqlx::query!(
+        "SELECT 1 as x FROM members WHERE mxid = $1 AND name = $2;",
+        mxid,
+        name
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .map(|res| res.is_some())
+}
+
+pub async fn get_member(mxid: &str, name: &str) -> sqlx::Result<Member> {
+    sqlx::query_as!(
+        Member,
+        "SELECT * FROM members WHERE mxid = $1 AND name = $2",
+        mxid,
+        name
+    )
+    .fetch_one(&*PK_POOL)
+    .await
+}
+
+/// Like [`get_member`], but `None` if the member doesn't exist.
+pub async fn find_member(mxid: &str, name: &str) -> sqlx::Result<Option<Member>> {
+    sqlx::query_as!(
+        Member,
+        "SELECT * FROM members WHERE mxid = $1 AND name = $2",
+        mxid,
+        name
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+}
+
+pub async fn list_members(mxid: &str) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar!("SELECT name FROM members WHERE mxid = $1;", mxid)
+        .fetch_all(&*PK_POOL)
+        .await
+}
+
+pub async fn set_fronters(mxid: &str, names: &[String]) -> sqlx::Result<()> {
+    let mut tx = PK_POOL.begin().await?;
+    sqlx::query!("DELETE FROM fronters WHERE mxid = $1", mxid)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO fronters (mxid, member, position)
+        SELECT $1, f.name, f.position::INTEGER
+        FROM unnest($2::TEXT[]) WITH ORDINALITY AS f(name, position)
+        "#,
+        mxid,
+        names
+    )
+    .execute(&mut tx)
+    .await?;
+    if let Some(first) = names.first() {
+        sqlx::query!(
+            "UPDATE users SET latched_member = $2 WHERE mxid = $1",
+            mxid,
+            first
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO switches (mxid, members)
+        SELECT $1, $2::TEXT[]
+        WHERE $2::TEXT[] IS DISTINCT FROM (
+            SELECT members FROM switches WHERE mxid = $1 ORDER BY started_at DESC LIMIT 1
+        )
+        "#,
+        mxid,
+        names
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await
+}
+
+pub async fn clear_fronters(mxid: &str) -> sqlx::Result<()> {
+    set_fronters(mxid, &[]).await
+}
+
+/// The user's most recent switches, newest first.
+pub async fn get_switches(mxid: &str, limit: i64) -> sqlx::Result<Vec<Switch>> {
+    sqlx::query_as!(
+        Switch,
+        r#"
+        SELECT
+            members,
+            to_char(started_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI "UTC"') AS "started_at!",
+            EXTRACT(EPOCH FROM
+                COALESCE(lead(started_at) OVER (ORDER BY started_at), now()) - started_at
+            )::BIGINT AS "duration!"
+        FROM switches
+        WHERE mxid = $1
+        ORDER BY switches.started_at DESC
+        LIMIT $2
+        "#,
+        mxid,
+        limit
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// Every switch the user has made, oldest first, with RFC 3339 start times.
+pub async fn get_all_switches(mxid: &str) -> sqlx::Result<Vec<(Vec<String>, String)>> {
+    sqlx::query!(
+        r#"
+        SELECT
+            members,
+            to_char(
+                started_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'
+            ) AS "started_at!"
+        FROM switches
+        WHERE mxid = $1
+        ORDER BY started_at
+        "#,
+        mxid
+    )
+    .map(|row| (row.members, row.started_at))
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// Adds a switch to the user's history unless there already is one at the time it started,
+/// given as RFC 3339 in `started_at` or milliseconds since the Unix epoch in `started_at_ms`.
+/// Returns `false` if it was already there.
+pub async fn add_switch(
+    mxid: &str,
+    members: &[String],
+    started_at: Option<&str>,
+    started_at_ms: Option<i64>,
+) -> sqlx::Result<bool> {
+    sqlx::query!(
+        r#"
+        WITH s AS (
+            SELECT COALESCE($3::TEXT::TIMESTAMPTZ, to_timestamp($4::BIGINT / 1000.0)) AS started_at
+        )
+        INSERT INTO switches (mxid, members, started_at)
+        SELECT $1, $2, s.started_at FROM s
+        WHERE NOT EXISTS (
+            SELECT 1 FROM switches WHERE mxid = $1 AND started_at = s.started_at
+        )
+        "#,
+        mxid,
+        members,
+        started_at,
+        started_at_ms
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|res| res.rows_affected() > 0)
+}
+
+pub async fn get_current_fronters(mxid: &str) -> anyhow::Result<Vec<Member>> {
+    sqlx::query_as!(
+        Member,
+        r#"
+        SELECT
+            m.mxid AS mxid,
+            m.name AS name,
+            m.display_name AS display_name,
+            m.avatar AS avatar,
+            m.activators AS activators,
+            m.track_account AS track_account,
+            m.proxy_tags AS proxy_tags,
+            m.pronouns AS pronouns,
+            m.description AS description,
+            m.color AS color,
+            m.birthday AS birthday,
+            m.notes AS notes,
+            m.private AS private,
+            m.private_fields AS private_fields
+        FROM fronters AS f
+            JOIN members AS m ON f.mxid = m.mxid AND f.member = m.name
+        WHERE f.mxid = $1
+        ORDER BY f.position
+        "#,
+        mxid
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+    .context("Error getting current fronters")
+}
+
+pub async fn get_cofront_settings(mxid: &str) -> anyhow::Result<CofrontSettings> {
+    let Some(row) = sqlx::query!(
+        "SELECT cofront_separator, cofront_avatar FROM users WHERE mxid = $1",
+        mxid
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .context("Error getting co-fronting settings")?
+    else {
+        return Ok(CofrontSettings::default());
+    };
+    Ok(CofrontSettings {
+        separator: row.cofront_separator,
+        avatar: row.cofront_avatar.parse()?,
+    })
+}
+
+pub async fn set_cofront_separator(mxid: &str, separator: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET cofront_separator = $2 WHERE mxid = $1",
+        mxid,
+        separator
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_cofront_avatar(mxid: &str, avatar: CofrontAvatar) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET cofront_avatar = $2 WHERE mxid = $1",
+        mxid,
+        avatar.as_str()
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_autoproxy(mxid: &str) -> anyhow::Result<AutoproxyMode> {
+    match sqlx::query_scalar!("SELECT autoproxy FROM users WHERE mxid = $1", mxid)
+        .fetch_optional(&*PK_POOL)
+        .await
+        .context("Error getting autoproxy mode")?
+    {
+        Some(mode) => mode.parse(),
+        None => Ok(AutoproxyMode::Front),
+    }
+}
+
+pub async fn set_autoproxy(mxid: &str, mode: AutoproxyMode) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET autoproxy = $2 WHERE mxid = $1",
+        mxid,
+        mode.as_str()
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+/// The user's fail policy, `None` if they use the server's default.
+pub async fn get_fail_policy(mxid: &str) -> anyhow::Result<Option<FailPolicy>> {
+    sqlx::query_scalar!("SELECT fail_policy FROM users WHERE mxid = $1", mxid)
+        .fetch_optional(&*PK_POOL)
+        .await
+        .context("Error getting fail policy")?
+        .flatten()
+        .map(|policy| policy.parse())
+        .transpose()
+}
+
+pub async fn set_fail_policy(mxid: &str, policy: Option<FailPolicy>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET fail_policy = $2 WHERE mxid = $1",
+        mxid,
+        policy.map(|policy| policy.as_str())
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_latched_member(mxid: &str, name: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE users SET latched_member = $2 WHERE mxid = $1",
+        mxid,
+        name
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_latched_member(mxid: &str) -> anyhow::Result<Option<Member>> {
+    sqlx::query_as!(
+        Member,
+        r#"
+        SELECT
+            m.mxid AS mxid,
+            m.name AS name,
+            m.display_name AS display_name,
+            m.avatar AS avatar,
+            m.activators AS activators,
+            m.track_account AS track_account,
+            m.proxy_tags AS proxy_tags,
+            m.pronouns AS pronouns,
+            m.description AS description,
+            m.color AS color,
+            m.birthday AS birthday,
+            m.notes AS notes,
+            m.private AS private,
+            m.private_fields AS private_fields
+        FROM users AS u
+            JOIN members AS m ON u.mxid = m.mxid AND u.latched_member = m.name
+        WHERE u.mxid = $1
+        "#,
+        mxid
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .context("Error getting latched member")
+}
+
+pub async fn set_room_fronter(mxid: &str, room_id: &str, name: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO room_fronters (mxid, room_id, member) VALUES ($1, $2, $3)
+        ON CONFLICT (mxid, room_id) DO UPDATE SET member = $3",
+        mxid,
+        room_id,
+        name
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn remove_room_fronter(mxid: &str, room_id: &str) -> sqlx::Result<bool> {
+    sqlx::query!(
+        "DELETE FROM room_fronters WHERE mxid = $1 AND room_id = $2",
+        mxid,
+        room_id
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|res| res.rows_affected() > 0)
+}
+
+pub async fn get_room_fronter(mxid: &str, room_id: &str) -> anyhow::Result<Option<Member>> {
+    sqlx::query_as!(
+        Member,
+        r#"
+        SELECT
+            m.mxid AS mxid,
+            m.name AS name,
+            m.display_name AS display_name,
+            m.avatar AS avatar,
+            m.activators AS activators,
+            m.track_account AS track_account,
+            m.proxy_tags AS proxy_tags,
+            m.pronouns AS pronouns,
+            m.description AS description,
+            m.color AS color,
+            m.birthday AS birthday,
+            m.notes AS notes,
+            m.private AS private,
+            m.private_fields AS private_fields
+        FROM room_fronters AS r
+            JOIN members AS m ON r.mxid = m.mxid AND r.member = m.name
+        WHERE r.mxid = $1 AND r.room_id = $2
+        "#,
+        mxid,
+        room_id
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .context("Error getting room fronter")
+}
+
+pub async fn list_room_fronters(mxid: &str) -> sqlx::Result<Vec<(String, String)>> {
+    sqlx::query!(
+        "SELECT room_id, member FROM room_fronters WHERE mxid = $1 ORDER BY room_id",
+        mxid
+    )
+    .map(|row| (row.room_id, row.member))
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// Finds the members and groups matching `activators`, in order. Returns `None` if one of them
+/// isn't an activator.
+pub async fn get_members_from_activators(
+    mxid: &str,
+    activators: &[String],
+) -> sqlx::Result<Option<Vec<Activated>>> {
+    let mut activated = Vec::with_capacity(activators.len());
+    for activator in activators {
+        match sqlx::query_scalar!(
+            "SELECT name FROM members WHERE mxid = $1 AND $2 = ANY(activators)",
+            mxid,
+            activator
+        )
+        .fetch_optional(&*PK_POOL)
+        .await?
+        {
+            Some(name) => activated.push(Activated::Member(name)),
+            None => {
+                let group = sqlx::query_scalar!(
+                    "SELECT name FROM groups WHERE mxid = $1 AND $2 = ANY(activators)",
+                    mxid,
+                    activator
+                )
+                .fetch_optional(&*PK_POOL)
+                .await?;
+                match group {
+                    Some(name) => {
+                        let members = get_group_members(mxid, &name).await?;
+                        activated.push(Activated::Group { name, members });
+                    }
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+    Ok(Some(activated))
+}
+
+/// Time fronted per member over the last `days` days, most fronted first. Fronts that started
+/// before the period only count the part inside it.
+pub async fn get_front_stats(mxid: &str, days: i32) -> sqlx::Result<Vec<FrontStats>> {
+    sqlx::query_as!(
+        FrontStats,
+        r#"
+        WITH s AS (
+            SELECT
+                members,
+                started_at,
+                COALESCE(lead(started_at) OVER (ORDER BY started_at), now()) AS ended_at
+            FROM switches
+            WHERE mxid = $1
+        )
+        SELECT
+            member AS "member!",
+            SUM(EXTRACT(EPOCH FROM
+                ended_at - GREATEST(started_at, now() - $2::INT * INTERVAL '1 day')
+            ))::BIGINT AS "seconds!",
+            COUNT(*) AS "fronts!",
+            MAX(EXTRACT(EPOCH FROM ended_at - started_at))::BIGINT AS "longest!"
+        FROM s, unnest(s.members) AS member
+        WHERE ended_at > now() - $2::INT * INTERVAL '1 day'
+        GROUP BY member
+        ORDER BY 2 DESC
+        "#,
+        mxid,
+        days
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// The hours of the day (UTC) the user switched most often in over the last `days` days.
+pub async fn get_switch_hours(mxid: &str, days: i32, limit: i64) -> sqlx::Result<Vec<(i32, i64)>> {
+    sqlx::query!(
+        r#"
+        SELECT
+            EXTRACT(HOUR FROM started_at AT TIME ZONE 'UTC')::INT AS "hour!",
+            COUNT(*) AS "switches!"
+        FROM switches
+        WHERE mxid = $1 AND started_at > now() - $2::INT * INTERVAL '1 day'
+        GROUP BY 1
+        ORDER BY 2 DESC, 1
+        LIMIT $3
+        "#,
+        mxid,
+        days,
+        limit
+    )
+    .map(|row| (row.hour, row.switches))
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// The longest fronts that were going on during the last `days` days.
+pub async fn get_longest_fronts(mxid: &str, days: i32, limit: i64) -> sqlx::Result<Vec<Switch>> {
+    sqlx::query_as!(
+        Switch,
+        r#"
+        SELECT
+            members AS "members!",
+            to_char(started_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI "UTC"') AS "started_at!",
+            EXTRACT(EPOCH FROM ended_at - started_at)::BIGINT AS "duration!"
+        FROM (
+            SELECT
+                members,
+                started_at,
+                COALESCE(lead(started_at) OVER (ORDER BY started_at), now()) AS ended_at
+            FROM switches
+            WHERE mxid = $1
+        ) AS s
+        WHERE ended_at > now() - $2::INT * INTERVAL '1 day' AND members <> '{}'
+        ORDER BY 3 DESC
+        LIMIT $3
+        "#,
+        mxid,
+        days,
+        limit
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+pub async fn update_tracking_member(mxid: &str, profile: &ProfileInfo) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE members
+        SET 
+            display_name = $2,
+            avatar = $3
+        WHERE mxid = $1
+        AND track_account = TRUE
+    "#,
+        mxid,
+        profile.display_name,
+        profile.avatar
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn toggle_tracking(mxid: &str, name: &str) -> sqlx::Result<bool> {
+    sqlx::query_scalar!(
+        "UPDATE members SET track_account = NOT track_account
+        WHERE mxid = $1 AND name = $2 RETURNING track_account",
+        mxid,
+        name
+    )
+    .fetch_one(&*PK_POOL)
+    .await
+}
+
+pub async fn is_room_ignored(mxid: &str, room_id: &str) -> sqlx::Result<bool> {
+    sqlx::query!(
+        "SELECT NULL AS x FROM ignored_rooms WHERE mxid = $1 AND room_id = $2",
+        mxid,
+        room_id
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .map(|res| res.is_some())
+}
+
+pub async fn ignore_room(mxid: &str, room_id: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO ignored_rooms (mxid, room_id) VALUES ($1, $2)",
+        mxid,
+        room_id
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn unignore_room(mxid: &str, room_id: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "DELETE FROM ignored_rooms WHERE mxid = $1 AND room_id = $2",
+        mxid,
+        room_id
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn list_ignored(mxid: &str) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar!("SELECT room_id FROM ignored_rooms WHERE mxid = $1", mxid)
+        .fetch_all(&*PK_POOL)
+        .await
+}
+
+pub async fn touch_active_room(mxid: &str, room_id: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO active_rooms (mxid, room_id)
+        SELECT $1, $2 WHERE EXISTS (SELECT 1 FROM users WHERE mxid = $1)
+        ON CONFLICT (mxid, room_id) DO UPDATE SET last_active = now()",
+        mxid,
+        room_id
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+/// The user's most recently active rooms, leaving out ignored rooms.
+pub async fn get_active_rooms(mxid: &str, limit: i64) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar!(
+        "SELECT a.room_id FROM active_rooms AS a
+        WHERE a.mxid = $1 AND NOT EXISTS (
+            SELECT 1 FROM ignored_rooms AS i WHERE i.mxid = a.mxid AND i.room_id = a.room_id
+        )
+        ORDER BY a.last_active DESC
+        LIMIT $2",
+        mxid,
+        limit
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+pub async fn create_group(mxid: &str, name: &str) -> anyhow::Result<()> {
+    sqlx::query!(
+        "INSERT INTO groups (mxid, name) VALUES ($1, $2);",
+        mxid,
+        name
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|_| ())
+    .map_err(|e| {
+        if e.not_unique() {
+            anyhow!(e).context("This group name is already in use")
+        } else {
+            e.into()
+        }
+    })
+}
+
+pub async fn group_exists(mxid: &str, name: &str) -> sqlx::Result<bool> {
+    sqlx::query!(
+        "SELECT 1 as x FROM groups WHERE mxid = $1 AND name = $2;",
+        mxid,
+        name
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+    .map(|res| res.is_some())
+}
+
+pub async fn get_group(mxid: &str, name: &str) -> sqlx::Result<Group> {
+    sqlx::query_as!(
+        Group,
+        "SELECT * FROM groups WHERE mxid = $1 AND name = $2",
+        mxid,
+        name
+    )
+    .fetch_one(&*PK_POOL)
+    .await
+}
+
+pub async fn list_groups(mxid: &str) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar!(
+        "SELECT name FROM groups WHERE mxid = $1 ORDER BY name",
+        mxid
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+pub async fn remove_group(mxid: &str, name: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "DELETE FROM groups WHERE mxid = $1 AND name = $2;",
+        mxid,
+        name
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|_| ())
+}
+
+pub async fn rename_group(mxid: &str, old_name: &str, new_name: &str) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE groups SET name = $3 WHERE mxid = $1 AND name = $2;",
+        mxid,
+        old_name,
+        new_name,
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|_| ())
+    .map_err(|e| {
+        if e.not_unique() {
+            anyhow!(e).context("This group name is already in use")
+        } else {
+            e.into()
+        }
+    })
+}
+
+pub async fn set_group_description(
+    mxid: &str,
+    name: &str,
+    description: Option<&str>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE groups SET description = $3 WHERE mxid = $1 AND name = $2",
+        mxid,
+        name,
+        description
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn add_group_activator(mxid: &str, name: &str, activator: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE groups SET activators = array_append(activators, $3) WHERE mxid = $1 AND name = $2",
+        mxid,
+        name,
+        activator
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn remove_group_activator(mxid: &str, name: &str, activator: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE groups SET activators = array_remove(activators, $3) WHERE mxid = $1 AND name = $2",
+        mxid,
+        name,
+        activator
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+/// Adds a member to a group, returns `false` if they were already in it.
+pub async fn add_group_member(mxid: &str, group: &str, member: &str) -> sqlx::Result<bool> {
+    sqlx::query!(
+        "INSERT INTO group_members (mxid, group_name, member) VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING",
+        mxid,
+        group,
+        member
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|res| res.rows_affected() > 0)
+}
+
+/// Removes a member from a group, returns `false` if they weren't in it.
+pub async fn remove_group_member(mxid: &str, group: &str, member: &str) -> sqlx::Result<bool> {
+    sqlx::query!(
+        "DELETE FROM group_members WHERE mxid = $1 AND group_name = $2 AND member = $3",
+        mxid,
+        group,
+        member
+    )
+    .execute(&*PK_POOL)
+    .await
+    .map(|res| res.rows_affected() > 0)
+}
+
+pub async fn get_group_members(mxid: &str, group: &str) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar!(
+        "SELECT member FROM group_members WHERE mxid = $1 AND group_name = $2 ORDER BY member",
+        mxid,
+        group
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// Links the user to a PluralKit system. Only switches made after linking are pushed. Linking to a
+/// different system than before forgets which members and switches were synced.
+pub async fn link_pk(mxid: &str, token: &str, system_id: &str) -> sqlx::Result<()> {
+    let mut tx = PK_POOL.begin().await?;
+    let same_system = sqlx::query_scalar!(
+        r#"SELECT system_id = $2 AS "same!" FROM pk_links WHERE mxid = $1"#,
+        mxid,
+        system_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .unwrap_or(false);
+    if !same_system {
+        sqlx::query!("DELETE FROM pk_member_sync WHERE mxid = $1", mxid)
+            .execute(&mut tx)
+            .await?;
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO pk_links (mxid, token, system_id, switches_pushed_until)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (mxid) DO UPDATE SET
+            token = EXCLUDED.token,
+            system_id = EXCLUDED.system_id,
+            switches_pushed_until = CASE
+                WHEN $4 THEN pk_links.switches_pushed_until
+                ELSE EXCLUDED.switches_pushed_until
+            END,
+            last_synced = CASE WHEN $4 THEN pk_links.last_synced END
+        "#,
+        mxid,
+        token,
+        system_id,
+        same_system
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await
+}
+
+/// Unlinks the user from PluralKit, returns `false` if they weren't linked.
+pub async fn unlink_pk(mxid: &str) -> sqlx::Result<bool> {
+    let mut tx = PK_POOL.begin().await?;
+    sqlx::query!("DELETE FROM pk_member_sync WHERE mxid = $1", mxid)
+        .execute(&mut tx)
+        .await?;
+    let unlinked = sqlx::query!("DELETE FROM pk_links WHERE mxid = $1", mxid)
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+        > 0;
+    tx.commit().await?;
+    Ok(unlinked)
+}
+
+pub async fn get_pk_link(mxid: &str) -> sqlx::Result<Option<PkLink>> {
+    sqlx::query_as!(
+        PkLink,
+        r#"
+        SELECT
+            mxid,
+            token,
+            system_id,
+            to_char(
+                switches_pushed_until AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'
+            ) AS switches_pushed_until,
+            to_char(
+                last_synced AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'
+            ) AS last_synced
+        FROM pk_links
+        WHERE mxid = $1
+        "#,
+        mxid
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
+}
+
+pub async fn list_pk_links() -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar!("SELECT mxid FROM pk_links ORDER BY mxid")
+        .fetch_all(&*PK_POOL)
+        .await
+}
+
+/// Marks the user's system as synced now, and records how far switches have been pushed.
+pub async fn set_pk_synced(mxid: &str, switches_pushed_until: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE pk_links SET
+            last_synced = now(),
+            switches_pushed_until = COALESCE($2::TEXT::TIMESTAMPTZ, switches_pushed_until)
+        WHERE mxid = $1
+        "#,
+        mxid,
+        switches_pushed_until
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_pk_member_syncs(mxid: &str) -> sqlx::Result<Vec<PkMemberSync>> {
+    sqlx::query_as!(
+        PkMemberSync,
+        r#"
+        SELECT
+            member,
+            pk_id,
+            to_char(
+                synced_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'
+            ) AS "synced_at!",
+            snapshot
+        FROM pk_member_sync
+        WHERE mxid = $1
+        ORDER BY member
+        "#,
+        mxid
+    )
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// Records that `member` was synced with the PluralKit member `pk_id` just now.
+pub async fn set_pk_member_synced(
+    mxid: &str,
+    member: &str,
+    pk_id: &str,
+    snapshot: &str,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO pk_member_sync (mxid, member, pk_id, snapshot) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (mxid, member) DO UPDATE SET
+            pk_id = EXCLUDED.pk_id,
+            synced_at = now(),
+            snapshot = EXCLUDED.snapshot
+        "#,
+        mxid,
+        member,
+        pk_id,
+        snapshot
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+/// Sets every profile field of a member, including clearing the ones that are `None`.
+pub async fn update_member_profile(member: &Member) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE members SET
+            display_name = $3,
+            avatar = $4,
+            pronouns = $5,
+            description = $6,
+            color = $7,
+            birthday = $8
+        WHERE mxid = $1 AND name = $2
+        "#,
+        member.mxid,
+        member.name,
+        member.display_name,
+        member.avatar,
+        member.pronouns,
+        member.description,
+        member.color,
+        member.birthday,
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+/// The user's switches that started after `after`, oldest first, with RFC 3339 start times.
+pub async fn get_switches_after(
+    mxid: &str,
+    after: Option<&str>,
+) -> sqlx::Result<Vec<(Vec<String>, String)>> {
+    sqlx::query!(
+        r#"
+        SELECT
+            members,
+            to_char(
+                started_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'
+            ) AS "started_at!"
+        FROM switches
+        WHERE mxid = $1 AND ($2::TEXT IS NULL OR started_at > $2::TEXT::TIMESTAMPTZ)
+        ORDER BY started_at
+        "#,
+        mxid,
+        after
+    )
+    .map(|row| (row.members, row.started_at))
+    .fetch_all(&*PK_POOL)
+    .await
+}
+
+/// Records which members a message was sent as.
+pub async fn log_message(
+    event_id: &str,
+    room_id: &str,
+    mxid: &str,
+    members: &[String],
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO sent_messages (event_id, room_id, mxid, member, position)
+        SELECT $1, $2, $3, m.member, m.position
+        FROM unnest($4::TEXT[]) WITH ORDINALITY AS m(member, position)
+        ON CONFLICT DO NOTHING
+        "#,
+        event_id,
+        room_id,
+        mxid,
+        members
+    )
+    .execute(&*PK_POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_sent_message(event_id: &str) -> sqlx::Result<Option<SentMessage>> {
+    sqlx::query_as!(
+        SentMessage,
+        r#"
+        SELECT
+            room_id,
+            mxid,
+            array_agg(member ORDER BY position) AS "members!"
+        FROM sent_messages
+        WHERE event_id = $1
+        GROUP BY room_id, mxid
+        "#,
+        event_id
+    )
+    .fetch_optional(&*PK_POOL)
+    .await
 }
@@ -0,0 +1,111 @@
+//! Looks up users, profiles, and rooms on the homeserver, either from Synapse's database or
+//! through the Client-Server API so homeservers other than Synapse work too. Access tokens can
+//! also be resolved with OAuth2 token introspection for homeservers that delegate auth.
+
+mod client_server;
+mod introspection;
+mod seen_tokens;
+mod synapse_db;
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+
+use crate::config::{HomeserverBackend, CONFIG};
+use crate::db::models::ProfileInfo;
+use crate::late_init::LateInit;
+
+pub use self::client_server::set_bot_token;
+
+pub static HOMESERVER: LateInit<Box<dyn Homeserver>> = LateInit::new();
+
+/// Shared by the backends that talk HTTP.
+static HTTP: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        // The bot and the proxy run on separate runtimes, so connections aren't reused between
+        // requests
+        .pool_max_idle_per_host(0)
+        .build()
+        .expect("Error building HTTP client")
+});
+
+/// Who an access token belongs to.
+#[derive(Debug, Clone)]
+pub struct TokenOwner {
+    pub user_id: String,
+    /// When the token stops working, `None` if it doesn't expire or the homeserver doesn't say
+    pub expires_at: Option<SystemTime>,
+}
+
+impl TokenOwner {
+    pub fn expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[cfg(test)]
+impl TokenOwner {
+    pub fn without_expiry(user_id: &str) -> Self {
+        TokenOwner {
+            user_id: user_id.to_owned(),
+            expires_at: None,
+        }
+    }
+}
+
+#[async_trait]
+pub trait Homeserver: Send + Sync {
+    /// The user an access token belongs to. Fails if the token isn't valid or has expired.
+    async fn user_id(&self, access_token: &str) -> anyhow::Result<TokenOwner>;
+
+    /// An access token for the user, so their join events can be updated without them sending a
+    /// message. `None` if they haven't sent one since Plural Kitty started.
+    async fn access_token(&self, mxid: &str) -> anyhow::Result<Option<String>>;
+
+    /// The user's global display name and avatar, empty if they aren't set.
+    async fn profile(&self, mxid: &str) -> anyhow::Result<ProfileInfo>;
+
+    /// An alias of the room to show instead of its ID.
+    async fn room_alias(&self, room_id: &str) -> anyhow::Result<String>;
+
+    /// Whether the user is currently joined to the room.
+    async fn is_room_member(&self, room_id: &str, mxid: &str) -> anyhow::Result<bool>;
+
+    /// Stops acting as a user with a token they logged out.
+    fn forget_token(&self, _access_token: &str) {}
+
+    /// Stops acting as a user with any token they were seen with, after they logged out everywhere.
+    fn forget_user(&self, _mxid: &str) {}
+}
+
+pub async fn init() -> anyhow::Result<()> {
+    let mut homeserver: Box<dyn Homeserver> = match CONFIG.synapse.backend() {
+        HomeserverBackend::SynapseDb => {
+            let Some(db) = &CONFIG.synapse.db else {
+                bail!("The synapse_db backend needs `synapse.db` to be set");
+            };
+            Box::new(
+                synapse_db::SynapseDb::connect(db)
+                    .await
+                    .context("Error connecting to synapse DB")?,
+            )
+        }
+        HomeserverBackend::ClientServer => {
+            Box::new(client_server::ClientServer::new(&CONFIG.synapse.host))
+        }
+    };
+    if let Some(oidc) = &CONFIG.oidc {
+        homeserver = Box::new(introspection::Introspection::new(
+            &oidc.introspection_endpoint,
+            &oidc.client_id,
+            oidc.client_secret().await?,
+            CONFIG.bot.user.server_name().as_str(),
+            homeserver,
+        ));
+    }
+    HOMESERVER.init(homeserver);
+    Ok(())
+}
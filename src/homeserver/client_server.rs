@@ -0,0 +1,261 @@
+//! Uses only the Matrix Client-Server API, so any homeserver works.
+//!
+//! Profiles, aliases, and memberships are looked up as the bot, so rooms the bot isn't in and
+//! that aren't world readable can't be seen. There is no API for getting another user's access
+//! token, so the last token each user sent a message with is remembered instead.
+
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Deserialize;
+
+use crate::db::models::ProfileInfo;
+
+use super::seen_tokens::{SeenTokens, SEEN_TOKENS_CAPACITY};
+use super::{Homeserver, TokenOwner, HTTP};
+
+/// The bot's access token, set once it has logged in.
+static BOT_TOKEN: RwLock<Option<String>> = RwLock::new(None);
+
+pub fn set_bot_token(token: String) {
+    *BOT_TOKEN.write().unwrap() = Some(token);
+}
+
+#[derive(Deserialize)]
+struct WhoAmI {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct Profile {
+    displayname: Option<String>,
+    avatar_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Aliases {
+    aliases: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Membership {
+    membership: String,
+}
+
+pub struct ClientServer {
+    base_url: String,
+    http: reqwest::Client,
+    tokens: Mutex<SeenTokens>,
+}
+
+impl ClientServer {
+    pub fn new(base_url: &str) -> Self {
+        ClientServer {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            http: HTTP.clone(),
+            tokens: Mutex::new(SeenTokens::new(SEEN_TOKENS_CAPACITY)),
+        }
+    }
+
+    fn get(&self, path: &str, token: &str) -> RequestBuilder {
+        self.http
+            .get(format!("{}/_matrix/client/v3{path}", self.base_url))
+            .bearer_auth(token)
+    }
+
+    fn bot_get(&self, path: &str) -> anyhow::Result<RequestBuilder> {
+        let token = BOT_TOKEN.read().unwrap().clone();
+        let token = token.ok_or_else(|| anyhow!("The bot hasn't logged in yet"))?;
+        Ok(self.get(path, &token))
+    }
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, NON_ALPHANUMERIC).to_string()
+}
+
+#[async_trait]
+impl Homeserver for ClientServer {
+    async fn user_id(&self, access_token: &str) -> anyhow::Result<TokenOwner> {
+        let response = self
+            .get("/account/whoami", access_token)
+            .send()
+            .await
+            .context("Error getting user from auth token")?;
+        if !response.status().is_success() {
+            bail!("Homeserver returned {} for auth token", response.status());
+        }
+        let user_id = response
+            .json::<WhoAmI>()
+            .await
+            .context("Error reading whoami response")?
+            .user_id;
+        let owner = TokenOwner {
+            user_id,
+            expires_at: None,
+        };
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(access_token, owner.clone());
+        Ok(owner)
+    }
+
+    async fn access_token(&self, mxid: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.tokens.lock().unwrap().get(mxid, SystemTime::now()))
+    }
+
+    async fn profile(&self, mxid: &str) -> anyhow::Result<ProfileInfo> {
+        let response = self
+            .bot_get(&format!("/profile/{}", encode(mxid)))?
+            .send()
+            .await
+            .with_context(|| format!("Error getting display name and avatar for {mxid}"))?;
+        let profile = match response.status() {
+            StatusCode::NOT_FOUND => Profile {
+                displayname: None,
+                avatar_url: None,
+            },
+            status if status.is_success() => response
+                .json()
+                .await
+                .with_context(|| format!("Error reading profile of {mxid}"))?,
+            status => bail!("Homeserver returned {status} for the profile of {mxid}"),
+        };
+        Ok(ProfileInfo {
+            display_name: profile.displayname.unwrap_or_default(),
+            avatar: profile.avatar_url.unwrap_or_default(),
+        })
+    }
+
+    async fn room_alias(&self, room_id: &str) -> anyhow::Result<String> {
+        let response = self
+            .bot_get(&format!("/rooms/{}/aliases", encode(room_id)))?
+            .send()
+            .await
+            .with_context(|| format!("Error getting alias for {room_id}"))?;
+        if !response.status().is_success() {
+            bail!(
+                "Homeserver returned {} for the aliases of {room_id}",
+                response.status()
+            );
+        }
+        response
+            .json::<Aliases>()
+            .await
+            .with_context(|| format!("Error reading aliases of {room_id}"))?
+            .aliases
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("{room_id} has no aliases"))
+    }
+
+    /// Rooms the bot can't see count as the user not being in them.
+    async fn is_room_member(&self, room_id: &str, mxid: &str) -> anyhow::Result<bool> {
+        let response = self
+            .bot_get(&format!(
+                "/rooms/{}/state/m.room.member/{}",
+                encode(room_id),
+                encode(mxid)
+            ))?
+            .send()
+            .await
+            .with_context(|| format!("Error checking if {mxid} is in {room_id}"))?;
+        match response.status() {
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(response
+                .json::<Membership>()
+                .await
+                .with_context(|| format!("Error reading membership of {mxid} in {room_id}"))?
+                .membership
+                == "join"),
+            status => bail!("Homeserver returned {status} checking if {mxid} is in {room_id}"),
+        }
+    }
+
+    fn forget_token(&self, access_token: &str) {
+        self.tokens.lock().unwrap().forget_token(access_token);
+    }
+
+    fn forget_user(&self, mxid: &str) {
+        self.tokens.lock().unwrap().forget_user(mxid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::Path;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+
+    use super::{set_bot_token, ClientServer};
+    use crate::homeserver::Homeserver;
+    use crate::stand_in;
+
+    async fn whoami(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+        match headers.get("authorization") {
+            Some(token) if token == "Bearer user-token" => {
+                Ok(Json(json!({"user_id": "@test:test.local"})))
+            }
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    async fn member(
+        Path((room_id, user_id)): Path<(String, String)>,
+    ) -> Result<Json<Value>, StatusCode> {
+        match (room_id.as_str(), user_id.as_str()) {
+            ("!room:test.local", "@test:test.local") => Ok(Json(json!({"membership": "join"}))),
+            ("!room:test.local", _) => Ok(Json(json!({"membership": "leave"}))),
+            _ => Err(StatusCode::FORBIDDEN),
+        }
+    }
+
+    /// Starts a stand-in homeserver on a free local port and returns its base URL.
+    fn stand_in() -> String {
+        let app = Router::new()
+            .route("/_matrix/client/v3/account/whoami", get(whoami))
+            .route(
+                "/_matrix/client/v3/rooms/:room_id/state/m.room.member/:user_id",
+                get(member),
+            );
+        stand_in::serve(app)
+    }
+
+    #[tokio::test]
+    async fn stand_in_server() {
+        let homeserver = ClientServer::new(&stand_in());
+        set_bot_token("bot-token".to_owned());
+
+        assert!(homeserver.user_id("wrong").await.is_err());
+        assert_eq!(
+            homeserver.access_token("@test:test.local").await.unwrap(),
+            None
+        );
+        let user_id = homeserver.user_id("user-token").await.unwrap().user_id;
+        assert_eq!(user_id, "@test:test.local");
+        assert_eq!(
+            homeserver.access_token(&user_id).await.unwrap().as_deref(),
+            Some("user-token")
+        );
+
+        assert!(homeserver
+            .is_room_member("!room:test.local", "@test:test.local")
+            .await
+            .unwrap());
+        assert!(!homeserver
+            .is_room_member("!room:test.local", "@other:test.local")
+            .await
+            .unwrap());
+        assert!(!homeserver
+            .is_room_member("!hidden:test.local", "@test:test.local")
+            .await
+            .unwrap());
+    }
+}
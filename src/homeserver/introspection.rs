@@ -0,0 +1,281 @@
+//! Resolves access tokens issued by an OIDC provider the homeserver delegates auth to (MSC3861),
+//! e.g. Matrix Authentication Service, with OAuth2 token introspection (RFC 7662). Everything
+//! else is left to the homeserver backend it wraps.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::db::models::ProfileInfo;
+
+use super::seen_tokens::{SeenTokens, SEEN_TOKENS_CAPACITY};
+use super::{Homeserver, TokenOwner, HTTP};
+
+/// Scopes that give a token access to the Client-Server API, unstable and stable.
+const API_SCOPES: &[&str] = &[
+    "urn:matrix:org.matrix.msc2967.client:api:*",
+    "urn:matrix:client:api:*",
+];
+
+#[derive(Deserialize)]
+struct IntrospectionResponse {
+    active: bool,
+    /// Space separated
+    scope: Option<String>,
+    /// The user's localpart
+    username: Option<String>,
+    /// Seconds since the Unix epoch
+    exp: Option<u64>,
+}
+
+pub struct Introspection {
+    endpoint: String,
+    client_id: String,
+    client_secret: Option<String>,
+    server_name: String,
+    http: reqwest::Client,
+    /// The provider's tokens never reach the backend it wraps
+    tokens: Mutex<SeenTokens>,
+    inner: Box<dyn Homeserver>,
+}
+
+impl Introspection {
+    pub fn new(
+        endpoint: &str,
+        client_id: &str,
+        client_secret: Option<String>,
+        server_name: &str,
+        inner: Box<dyn Homeserver>,
+    ) -> Self {
+        Introspection {
+            endpoint: endpoint.to_owned(),
+            client_id: client_id.to_owned(),
+            client_secret,
+            server_name: server_name.to_owned(),
+            http: HTTP.clone(),
+            tokens: Mutex::new(SeenTokens::new(SEEN_TOKENS_CAPACITY)),
+            inner,
+        }
+    }
+
+    async fn introspect(&self, access_token: &str) -> anyhow::Result<IntrospectionResponse> {
+        let mut form = vec![("token", access_token), ("token_type_hint", "access_token")];
+        let request = self.http.post(&self.endpoint);
+        let request = match &self.client_secret {
+            Some(secret) => request.basic_auth(&self.client_id, Some(secret)),
+            None => {
+                form.push(("client_id", self.client_id.as_str()));
+                request
+            }
+        };
+        let response = request.form(&form).send().await.with_context(|| {
+            format!("Error contacting introspection endpoint {}", self.endpoint)
+        })?;
+        if !response.status().is_success() {
+            bail!(
+                "Introspection endpoint returned {} for auth token",
+                response.status()
+            );
+        }
+        response
+            .json()
+            .await
+            .context("Error reading introspection response")
+    }
+}
+
+#[async_trait]
+impl Homeserver for Introspection {
+    async fn user_id(&self, access_token: &str) -> anyhow::Result<TokenOwner> {
+        let response = self.introspect(access_token).await?;
+        if !response.active {
+            bail!("Auth token isn't active");
+        }
+        let scopes = response.scope.unwrap_or_default();
+        if !scopes.split(' ').any(|scope| API_SCOPES.contains(&scope)) {
+            bail!("Auth token doesn't have access to the Matrix API");
+        }
+        let username = response
+            .username
+            .ok_or_else(|| anyhow!("Introspection response has no username"))?;
+        let owner = TokenOwner {
+            user_id: format!("@{username}:{}", self.server_name),
+            expires_at: response
+                .exp
+                .map(|exp| UNIX_EPOCH + Duration::from_secs(exp)),
+        };
+        if owner.expired(SystemTime::now()) {
+            bail!("Access token for {} has expired", owner.user_id);
+        }
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(access_token, owner.clone());
+        Ok(owner)
+    }
+
+    async fn access_token(&self, mxid: &str) -> anyhow::Result<Option<String>> {
+        let seen = self.tokens.lock().unwrap().get(mxid, SystemTime::now());
+        match seen {
+            Some(token) => Ok(Some(token)),
+            None => self.inner.access_token(mxid).await,
+        }
+    }
+
+    async fn profile(&self, mxid: &str) -> anyhow::Result<ProfileInfo> {
+        self.inner.profile(mxid).await
+    }
+
+    async fn room_alias(&self, room_id: &str) -> anyhow::Result<String> {
+        self.inner.room_alias(room_id).await
+    }
+
+    async fn is_room_member(&self, room_id: &str, mxid: &str) -> anyhow::Result<bool> {
+        self.inner.is_room_member(room_id, mxid).await
+    }
+
+    fn forget_token(&self, access_token: &str) {
+        self.tokens.lock().unwrap().forget_token(access_token);
+        self.inner.forget_token(access_token);
+    }
+
+    fn forget_user(&self, mxid: &str) {
+        self.tokens.lock().unwrap().forget_user(mxid);
+        self.inner.forget_user(mxid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use anyhow::bail;
+    use async_trait::async_trait;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::{Form, Json, Router};
+    use serde_json::{json, Value};
+
+    use super::Introspection;
+    use crate::db::models::ProfileInfo;
+    use crate::homeserver::{Homeserver, TokenOwner};
+    use crate::stand_in;
+
+    struct NoHomeserver;
+
+    #[async_trait]
+    impl Homeserver for NoHomeserver {
+        async fn user_id(&self, _: &str) -> anyhow::Result<TokenOwner> {
+            bail!("Not in the database")
+        }
+
+        async fn access_token(&self, _: &str) -> anyhow::Result<Option<String>> {
+            Ok(None)
+        }
+
+        async fn profile(&self, _: &str) -> anyhow::Result<ProfileInfo> {
+            bail!("Not in the database")
+        }
+
+        async fn room_alias(&self, _: &str) -> anyhow::Result<String> {
+            bail!("Not in the database")
+        }
+
+        async fn is_room_member(&self, _: &str, _: &str) -> anyhow::Result<bool> {
+            bail!("Not in the database")
+        }
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    async fn introspect(
+        headers: HeaderMap,
+        Form(form): Form<Vec<(String, String)>>,
+    ) -> Result<Json<Value>, StatusCode> {
+        match headers.get("authorization") {
+            // `client:secret`
+            Some(auth) if auth == "Basic Y2xpZW50OnNlY3JldA==" => {}
+            _ => return Err(StatusCode::UNAUTHORIZED),
+        }
+        let token = form
+            .iter()
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value.as_str());
+        let scope = "openid urn:matrix:org.matrix.msc2967.client:api:*";
+        Ok(Json(match token {
+            Some("valid") => json!({
+                "active": true,
+                "scope": scope,
+                "username": "test",
+                "exp": now_secs() + 300,
+            }),
+            Some("expired") => json!({
+                "active": true,
+                "scope": scope,
+                "username": "test",
+                "exp": now_secs() - 300,
+            }),
+            Some("no-api") => json!({"active": true, "scope": "openid", "username": "test"}),
+            _ => json!({"active": false}),
+        }))
+    }
+
+    /// Starts a stand-in introspection endpoint on a free local port and returns its URL.
+    fn stand_in() -> String {
+        let app = Router::new().route("/oauth2/introspect", post(introspect));
+        stand_in::serve(app) + "oauth2/introspect"
+    }
+
+    #[tokio::test]
+    async fn stand_in_server() {
+        let endpoint = stand_in();
+        let introspection = Introspection::new(
+            &endpoint,
+            "client",
+            Some("secret".to_owned()),
+            "test.local",
+            Box::new(NoHomeserver),
+        );
+
+        let owner = introspection.user_id("valid").await.unwrap();
+        assert_eq!(owner.user_id, "@test:test.local");
+        let expires_at = owner.expires_at.unwrap();
+        assert!(expires_at > SystemTime::now() + Duration::from_secs(200));
+        assert_eq!(
+            introspection
+                .access_token("@test:test.local")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("valid")
+        );
+
+        for token in ["expired", "no-api", "revoked"] {
+            assert!(introspection.user_id(token).await.is_err(), "{token}");
+        }
+        assert_eq!(
+            introspection
+                .access_token("@other:test.local")
+                .await
+                .unwrap(),
+            None
+        );
+
+        let wrong_secret = Introspection::new(
+            &endpoint,
+            "client",
+            Some("wrong".to_owned()),
+            "test.local",
+            Box::new(NoHomeserver),
+        );
+        assert!(wrong_secret.user_id("valid").await.is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! The last access token each user sent through the proxy, so their rooms can be updated
+//! after a switch with a token they gave Plural Kitty themselves.
+//!
+//! Unlike the proxy's token cache, which only keeps hashes, the tokens are kept as they are
+//! because they have to be sent back to the homeserver. That is the cost of updating rooms
+//! right after a switch: they only ever live in memory, at most one per user, and are
+//! forgotten once they are logged out, once they expire, when the user seen longest ago makes
+//! room for new ones, or when Plural Kitty restarts.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use super::TokenOwner;
+
+/// How many users' tokens are remembered at once.
+pub const SEEN_TOKENS_CAPACITY: usize = 10_000;
+
+struct Seen {
+    token: String,
+    owner: TokenOwner,
+    /// Position in [`SeenTokens::recent`]
+    seen_at: u64,
+}
+
+pub struct SeenTokens {
+    capacity: usize,
+    users: HashMap<String, Seen>,
+    /// Users by when their token was last seen, oldest first
+    recent: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl SeenTokens {
+    pub fn new(capacity: usize) -> Self {
+        SeenTokens {
+            capacity,
+            users: HashMap::new(),
+            recent: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn insert(&mut self, token: &str, owner: TokenOwner) {
+        self.forget_user(&owner.user_id);
+        while self.users.len() >= self.capacity {
+            let Some((_, oldest)) = self.recent.pop_first() else {
+                break;
+            };
+            self.users.remove(&oldest);
+        }
+        self.tick += 1;
+        self.recent.insert(self.tick, owner.user_id.clone());
+        self.users.insert(
+            owner.user_id.clone(),
+            Seen {
+                token: token.to_owned(),
+                owner,
+                seen_at: self.tick,
+            },
+        );
+    }
+
+    /// The user's last token, unless it has expired.
+    pub fn get(&self, user_id: &str, now: SystemTime) -> Option<String> {
+        let seen = self.users.get(user_id)?;
+        (!seen.owner.expired(now)).then(|| seen.token.clone())
+    }
+
+    pub fn forget_token(&mut self, token: &str) {
+        let user_id = self
+            .users
+            .iter()
+            .find(|(_, seen)| seen.token == token)
+            .map(|(user_id, _)| user_id.clone());
+        if let Some(user_id) = user_id {
+            self.forget_user(&user_id);
+        }
+    }
+
+    pub fn forget_user(&mut self, user_id: &str) {
+        if let Some(seen) = self.users.remove(user_id) {
+            self.recent.remove(&seen.seen_at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::SeenTokens;
+    use crate::homeserver::TokenOwner;
+
+    #[test]
+    fn evicts_and_forgets() {
+        let now = SystemTime::now();
+        let mut tokens = SeenTokens::new(2);
+        tokens.insert("a1", TokenOwner::without_expiry("@a:test.local"));
+        tokens.insert("b1", TokenOwner::without_expiry("@b:test.local"));
+        tokens.insert("a2", TokenOwner::without_expiry("@a:test.local"));
+        tokens.insert("c1", TokenOwner::without_expiry("@c:test.local"));
+        assert_eq!(tokens.get("@a:test.local", now).as_deref(), Some("a2"));
+        assert_eq!(tokens.get("@b:test.local", now), None);
+        assert_eq!(tokens.get("@c:test.local", now).as_deref(), Some("c1"));
+
+        tokens.forget_token("a2");
+        assert_eq!(tokens.get("@a:test.local", now), None);
+        tokens.forget_user("@c:test.local");
+        assert_eq!(tokens.get("@c:test.local", now), None);
+
+        let expired = TokenOwner {
+            expires_at: Some(now),
+            ..TokenOwner::without_expiry("@d:test.local")
+        };
+        tokens.insert("d1", expired);
+        assert_eq!(tokens.get("@d:test.local", now), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Reads Synapse's own tables.
+//!
+//! Synapse's `access_tokens` table would give a token for any user, but acting as someone with a
+//! token they didn't send through the proxy isn't allowed, so the last token each user sent a
+//! message with is remembered instead, like the Client-Server backend does.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::config::DbInfo;
+use crate::db::models::ProfileInfo;
+
+use super::seen_tokens::{SeenTokens, SEEN_TOKENS_CAPACITY};
+use super::{Homeserver, TokenOwner};
+
+pub struct SynapseDb {
+    pool: Pool<Postgres>,
+    tokens: Mutex<SeenTokens>,
+}
+
+impl SynapseDb {
+    pub async fn connect(db: &DbInfo) -> anyhow::Result<Self> {
+        let db_opts = db.db_con_opts().await?;
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(db_opts.clone())
+            .await
+            .context(format!("Error connection to synapse DB at `{db_opts:?}`"))?;
+        Ok(SynapseDb {
+            pool,
+            tokens: Mutex::new(SeenTokens::new(SEEN_TOKENS_CAPACITY)),
+        })
+    }
+}
+
+#[async_trait]
+impl Homeserver for SynapseDb {
+    async fn user_id(&self, access_token: &str) -> anyhow::Result<TokenOwner> {
+        let (user_id, valid_until_ms) =
+            sqlx::query("SELECT user_id, valid_until_ms FROM access_tokens WHERE token = $1")
+                .bind(access_token)
+                .map(|row| {
+                    (
+                        row.get::<String, usize>(0),
+                        row.get::<Option<i64>, usize>(1),
+                    )
+                })
+                .fetch_one(&self.pool)
+                .await
+                .context("Error getting user from auth token")?;
+        let owner = TokenOwner {
+            user_id,
+            expires_at: valid_until_ms
+                .map(|ms| UNIX_EPOCH + Duration::from_millis(ms.max(0) as u64)),
+        };
+        if owner.expired(SystemTime::now()) {
+            bail!("Access token for {} has expired", owner.user_id);
+        }
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(access_token, owner.clone());
+        Ok(owner)
+    }
+
+    async fn access_token(&self, mxid: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.tokens.lock().unwrap().get(mxid, SystemTime::now()))
+    }
+
+    async fn profile(&self, mxid: &str) -> anyhow::Result<ProfileInfo> {
+        sqlx::query_as(
+            r#"SELECT 
+            COALESCE(displayname, '') AS displayname, COALESCE(avatar_url, '') AS avatar_url
+            FROM profiles WHERE full_user_id = $1"#,
+        )
+        .bind(mxid)
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| format!("Error getting display name and avatar for {mxid}"))
+    }
+
+    async fn room_alias(&self, room_id: &str) -> anyhow::Result<String> {
+        sqlx::query_scalar("SELECT room_alias FROM room_aliases WHERE room_id = $1")
+            .bind(room_id)
+            .fetch_one(&self.pool)
+            .await
+            .with_context(|| format!("Error getting alias for {room_id}"))
+    }
+
+    async fn is_room_member(&self, room_id: &str, mxid: &str) -> anyhow::Result<bool> {
+        sqlx::query(
+            r#"SELECT 1 FROM current_state_events
+            WHERE room_id = $1 AND type = 'm.room.member' AND state_key = $2 AND membership = 'join'"#,
+        )
+        .bind(room_id)
+        .bind(mxid)
+        .fetch_optional(&self.pool)
+        .await
+        .map(|row| row.is_some())
+        .with_context(|| format!("Error checking if {mxid} is in {room_id}"))
+    }
+
+    fn forget_token(&self, access_token: &str) {
+        self.tokens.lock().unwrap().forget_token(access_token);
+    }
+
+    fn forget_user(&self, mxid: &str) {
+        self.tokens.lock().unwrap().forget_user(mxid);
+    }
+}
@@ -1,8 +1,11 @@
 mod bot;
 mod config;
 mod db;
+mod homeserver;
 mod late_init;
 mod proxy;
+#[cfg(test)]
+mod stand_in;
 
 use std::sync::atomic::Ordering;
 
@@ -29,6 +32,9 @@ fn main() {
 #[tokio::main]
 async fn init() -> anyhow::Result<()> {
     db::init().await.context("Error connecting to bot DB")?;
+    homeserver::init()
+        .await
+        .context("Error setting up homeserver backend")?;
     Ok(())
 }
 
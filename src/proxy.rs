@@ -2,42 +2,84 @@ use anyhow::Context;
 use axum::{
     extract::{Path, State},
     headers::{authorization::Bearer, Authorization},
-    http::{uri::Uri, Request, Response},
-    routing::put,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        uri::Uri,
+        Request, Response,
+    },
+    routing::{post, put},
     Router, TypedHeader,
 };
 use hyper::{client::HttpConnector, Body, StatusCode};
 use matrix_sdk::ruma::{
-    api::client::state::{get_state_events_for_key, send_state_event},
+    api::client::{
+        account::whoami,
+        state::{get_state_events_for_key, send_state_event},
+    },
     events::{room::member::RoomMemberEventContent, AnyStateEventContent, StateEventType},
-    OwnedRoomId,
+    OwnedRoomId, OwnedUserId,
 };
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
 };
 use tokio::sync::{Mutex, RwLock};
 
-use crate::{config::CONFIG, db::queries};
+use crate::{
+    config::CONFIG,
+    db::{
+        models::{AutoproxyMode, FailPolicy, Member},
+        queries,
+    },
+    homeserver::{TokenOwner, HOMESERVER},
+};
+
+pub mod appservice;
+pub mod identity;
+pub mod rollout;
+pub mod tags;
+pub mod token_cache;
+
+use self::appservice::{masquerade_param, Appservices};
+use self::token_cache::{TokenCache, TOKEN_CAPACITY, TOKEN_TTL};
 
 #[derive(Debug, Clone)]
 struct AppState {
     client: HttpClient,
-    user_ids: Arc<RwLock<HashMap<String, String>>>,
-    update_locks: Arc<RwLock<HashMap<String, Arc<Mutex<()>>>>>,
+    user_ids: Arc<Mutex<TokenCache>>,
+    appservices: Arc<Appservices>,
 }
 
 type HttpClient = hyper::client::Client<HttpConnector, Body>;
 
 pub static STARTED: AtomicBool = AtomicBool::new(false);
 
+/// Held while a user's join event is being updated so the proxy and rollouts don't send
+/// duplicate join events.
+static UPDATE_LOCKS: Lazy<RwLock<HashMap<String, Arc<Mutex<()>>>>> = Lazy::new(Default::default);
+
+/// Rooms, by user and room ID, where the account's own profile has been put back and nobody has
+/// fronted since, so messages sent there while nobody is fronting don't need the homeserver.
+static ACCOUNT_ROOMS: Lazy<Mutex<HashSet<(String, String)>>> = Lazy::new(Default::default);
+/// How many rooms [`ACCOUNT_ROOMS`] holds before it is emptied.
+const ACCOUNT_ROOMS_CAPACITY: usize = 10_000;
+
+/// How long the `retry` fail policy waits before its first retry, each retry waits longer.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
 #[tokio::main]
 pub async fn init() -> anyhow::Result<()> {
     let client = HttpClient::new();
+    let appservices =
+        Appservices::load(&CONFIG.appservices, CONFIG.bot.user.server_name().as_str())
+            .context("Error loading appservice registrations")?;
     let state = AppState {
         client,
-        user_ids: Default::default(),
-        update_locks: Default::default(),
+        user_ids: Arc::new(Mutex::new(TokenCache::new(TOKEN_CAPACITY, TOKEN_TTL))),
+        appservices: Arc::new(appservices),
     };
 
     let app = Router::new()
@@ -45,6 +87,14 @@ pub async fn init() -> anyhow::Result<()> {
             "/_matrix/client/:version/rooms/:room_id/send/:event_type/:txn_id",
             put(msg_send_handler).options(passthrough_handler),
         )
+        .route(
+            "/_matrix/client/:version/logout",
+            post(logout_handler).options(passthrough_handler),
+        )
+        .route(
+            "/_matrix/client/:version/logout/all",
+            post(logout_handler).options(passthrough_handler),
+        )
         .fallback(passthrough_handler)
         .with_state(state);
 
@@ -56,115 +106,424 @@ pub async fn init() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn update_indentity(
-    AppState {
-        client,
-        user_ids,
-        update_locks,
-    }: &AppState,
-    room_id: String,
-    auth: Authorization<Bearer>,
-) -> anyhow::Result<()> {
-    let read_lock = user_ids.read().await;
-    let user_id = match read_lock.get(auth.token()) {
-        Some(user_id) => user_id.clone(),
+/// Looks up who a token belongs to, asking the homeserver if it isn't cached.
+async fn get_user_id(user_ids: &Mutex<TokenCache>, token: &str) -> anyhow::Result<String> {
+    if let Some(user_id) = user_ids.lock().await.get(token, Instant::now()) {
+        return Ok(user_id);
+    }
+    let owner = HOMESERVER.user_id(token).await?;
+    let user_id = owner.user_id.clone();
+    user_ids.lock().await.insert(token, owner, Instant::now());
+    Ok(user_id)
+}
+
+/// Who a request is sent by.
+struct Sender {
+    user_id: String,
+    /// An appservice is sending as one of its users, so requests made for the user have to say
+    /// who they are for
+    masquerading: bool,
+}
+
+/// Works out who a request is sent by, including appservices sending as their users with
+/// `?user_id=`.
+async fn get_sender(
+    state: &AppState,
+    token: &str,
+    masquerade: Option<&str>,
+) -> anyhow::Result<Sender> {
+    if let Some(appservice) = state.appservices.find(token) {
+        return Ok(Sender {
+            user_id: appservice.user_id(masquerade)?,
+            masquerading: true,
+        });
+    }
+    let Some(masquerade) = masquerade else {
+        return Ok(Sender {
+            user_id: get_user_id(&state.user_ids, token).await?,
+            masquerading: false,
+        });
+    };
+    // Appservices that aren't configured are checked with the homeserver. Homeservers ignore
+    // `user_id` for other tokens, so the token's own user is returned for them.
+    let key = format!("{token}\n{masquerade}");
+    let cached = state.user_ids.lock().await.get(&key, Instant::now());
+    let user_id = match cached {
+        Some(user_id) => user_id,
         None => {
-            drop(read_lock);
-            let user_id = queries::get_synapse_user(auth.token()).await?;
-            let mut write_lock = user_ids.write().await;
-            write_lock.insert(auth.token().to_owned(), user_id.clone());
+            let user_id = whoami_as(&state.client, token, masquerade).await?;
+            let owner = TokenOwner {
+                user_id: user_id.clone(),
+                expires_at: None,
+            };
+            state
+                .user_ids
+                .lock()
+                .await
+                .insert(&key, owner, Instant::now());
             user_id
         }
     };
+    Ok(Sender {
+        masquerading: user_id == masquerade,
+        user_id,
+    })
+}
 
-    if let Some(member) = queries::get_current_fronter(&user_id)
+/// Asks the homeserver who a request with `token` and `?user_id=` is sent as.
+async fn whoami_as(client: &HttpClient, token: &str, user_id: &str) -> anyhow::Result<String> {
+    let user_id: OwnedUserId = user_id
+        .parse()
+        .with_context(|| format!("{user_id} isn't a valid user ID"))?;
+    let client = matrix_sdk::ruma::Client::builder()
+        .homeserver_url(CONFIG.synapse.host.to_owned())
+        .access_token(Some(token.to_owned()))
+        .http_client(client.to_owned())
         .await
-        .context("Error getting user's current member")?
-    {
-        if queries::is_room_ignored(&user_id, &room_id).await? {
-            tracing::debug!("Message in ignored room");
-            return Ok(());
-        }
-        // ** This ensures multiple join evens aren't sent if the users sends a second message
-        // before the join event is posted.
-        let update_lock = {
-            let read_lock = update_locks.read().await;
-            match read_lock.get(&user_id) {
-                Some(lock) => lock.to_owned(),
-                None => {
-                    drop(read_lock);
-                    let mut write_lock = update_locks.write().await;
-                    let update_lock = Arc::new(Mutex::new(()));
-                    write_lock.insert(user_id.clone(), update_lock.clone());
-                    update_lock
-                }
-            }
-        };
-        let _lock = update_lock.lock().await;
-        // **
-        let client = matrix_sdk::ruma::Client::builder()
-            .homeserver_url(CONFIG.synapse.host.to_owned())
-            .access_token(Some(auth.token().to_owned()))
-            .http_client(client.to_owned())
-            .await
-            .context("Error building proxy matrix client")?;
+        .context("Error building proxy matrix client")?;
+    let response = client
+        .send_request_as(&user_id, whoami::v3::Request::new())
+        .await
+        .with_context(|| format!("Error checking who is sending as {user_id}"))?;
+    Ok(response.user_id.to_string())
+}
 
-        let room_id: OwnedRoomId = room_id
-            .parse()
-            .with_context(|| format!("room id {room_id} not valid room id"))?;
-        let mut join_event: RoomMemberEventContent = client
-            .send_request(get_state_events_for_key::v3::Request::new(
-                room_id.clone(),
-                StateEventType::RoomMember,
-                user_id.to_owned(),
-            ))
-            .await
-            .with_context(|| format!("Error getting join event for user {user_id}"))?
-            .content
-            .deserialize_as()
-            .with_context(|| format!("Error deserializing join event for {user_id}"))?;
+/// What [`proxy_with_policy`] did to a message.
+#[derive(Default)]
+struct Proxied {
+    /// The new event body if it was rewritten
+    body: Option<Vec<u8>>,
+    user_id: String,
+    /// Names of the members the message is sent as, empty if it is sent as the account
+    members: Vec<String>,
+}
 
-        let mut changed = false;
+/// A message whose proxy tags have been stripped, waiting for its sender's identity to be
+/// updated in the room.
+struct Prepared {
+    /// The new event body if it was rewritten
+    body: Option<Vec<u8>>,
+    user_id: String,
+    masquerading: bool,
+    tagged_member: Option<Member>,
+}
 
-        match (member.display_name, &join_event.displayname) {
-            (Some(member_name), Some(curr_name)) if member_name != *curr_name => {
-                join_event.displayname = Some(member_name);
-                changed = true;
-            }
-            (Some(member_name), None) => {
-                join_event.displayname = Some(member_name);
-                changed = true;
+/// Works out who a message is sent by and strips its proxy tags. `None` if it is sent unchanged.
+async fn prepare_message(
+    state: &AppState,
+    room_id: &str,
+    event_type: &str,
+    auth: &Authorization<Bearer>,
+    masquerade: Option<&str>,
+    body: &[u8],
+) -> anyhow::Result<Option<Prepared>> {
+    let Sender {
+        user_id,
+        masquerading,
+    } = get_sender(state, auth.token(), masquerade).await?;
+    // Most of an appservice's users are bridged from elsewhere and never set Plural Kitty up
+    if masquerading && !queries::user_exists(&user_id).await? {
+        return Ok(None);
+    }
+    if queries::is_room_ignored(&user_id, room_id).await? {
+        tracing::debug!("Message in ignored room");
+        return Ok(None);
+    }
+    let mut new_body = None;
+    let mut tagged_member = None;
+    // Messages in encrypted rooms are sent as `m.room.encrypted`, their body is ciphertext so
+    // proxy tags can't be read from them.
+    if event_type == "m.room.message" {
+        let members = queries::get_tagged_members(&user_id)
+            .await
+            .context("Error getting user's proxy tags")?;
+        if !members.is_empty() {
+            let mut content: Map<String, Value> =
+                serde_json::from_slice(body).context("Error parsing message event")?;
+            if let Some(member) = tags::strip_tags(&members, &mut content) {
+                tracing::debug!("Message matched proxy tag for {}", member.name);
+                queries::set_latched_member(&user_id, &member.name)
+                    .await
+                    .context("Error latching member")?;
+                tagged_member = Some(member.clone());
+                new_body =
+                    Some(serde_json::to_vec(&content).context("Error serializing message event")?);
             }
-            _ => {}
         }
-        match (member.avatar, &join_event.avatar_url) {
-            (Some(member_avatar), Some(curr_avatar)) if member_avatar != *curr_avatar => {
-                join_event.avatar_url = Some(member_avatar.into());
-                changed = true;
-            }
-            (Some(member_avatar), None) => {
-                join_event.avatar_url = Some(member_avatar.into());
-                changed = true;
+    }
+    // Only rollouts read active rooms, so the message doesn't wait for this
+    tokio::spawn({
+        let (user_id, room_id) = (user_id.clone(), room_id.to_owned());
+        async move {
+            if let Err(e) = queries::touch_active_room(&user_id, &room_id).await {
+                tracing::error!("Error recording active room {room_id} for {user_id}: {e:#}");
             }
-            _ => {}
-        }
-
-        if changed {
-            client
-                .send_request(
-                    send_state_event::v3::Request::new(
-                        room_id,
-                        &user_id,
-                        &AnyStateEventContent::from(join_event),
-                    )
-                    .with_context(|| format!("Error serializing join event for {user_id}"))?,
+        }
+    });
+    Ok(Some(Prepared {
+        body: new_body,
+        user_id,
+        masquerading,
+        tagged_member,
+    }))
+}
+
+/// Strips proxy tags from a message and makes sure the sender has the right identity in the
+/// room before it is sent. When that fails the message is either sent anyway, or not sent after
+/// retrying the identity update if the sender's fail policy asks for it.
+async fn proxy_with_policy(
+    state: &AppState,
+    room_id: &str,
+    event_type: &str,
+    auth: &Authorization<Bearer>,
+    masquerade: Option<&str>,
+    body: &[u8],
+) -> anyhow::Result<Proxied> {
+    // Only looked up once something goes wrong
+    let mut policy = None;
+    let e = match prepare_message(state, room_id, event_type, auth, masquerade, body).await {
+        Ok(None) => return Ok(Proxied::default()),
+        Ok(Some(prepared)) => {
+            let mut retries = 0;
+            loop {
+                let e = match update_indentity(
+                    &state.client,
+                    &prepared.user_id,
+                    room_id.to_owned(),
+                    auth.token(),
+                    prepared.masquerading,
+                    prepared.tagged_member.clone(),
                 )
                 .await
-                .with_context(|| format!("Error sending new join event for {user_id}"))?;
+                {
+                    Ok(members) => {
+                        return Ok(Proxied {
+                            body: prepared.body,
+                            user_id: prepared.user_id,
+                            members: members.into_iter().map(|member| member.name).collect(),
+                        })
+                    }
+                    Err(e) => e,
+                };
+                let current = match policy {
+                    Some(policy) => policy,
+                    None => *policy.insert(fail_policy(state, auth.token(), masquerade).await),
+                };
+                if current != FailPolicy::Retry || retries >= CONFIG.fail_retries {
+                    break e;
+                }
+                retries += 1;
+                tracing::warn!(
+                    "Error updating identity, retrying ({retries}/{}): {e:#}",
+                    CONFIG.fail_retries
+                );
+                tokio::time::sleep(RETRY_DELAY * retries).await;
+            }
         }
+        Err(e) => e,
+    };
+    let policy = match policy {
+        Some(policy) => policy,
+        None => fail_policy(state, auth.token(), masquerade).await,
+    };
+    match policy {
+        FailPolicy::FailOpen => {
+            tracing::error!("Error handling message event: {e:#}");
+            Ok(Proxied::default())
+        }
+        FailPolicy::FailClosed | FailPolicy::Retry => Err(e),
     }
+}
 
-    Ok(())
+/// The fail policy of whoever sent a message. Messages from senders that can't be worked out are
+/// passed on so the homeserver can reject them itself if the token isn't valid, and users without
+/// a system have no member to be shown as by mistake.
+async fn fail_policy(state: &AppState, token: &str, masquerade: Option<&str>) -> FailPolicy {
+    let Ok(Sender { user_id, .. }) = get_sender(state, token, masquerade).await else {
+        return FailPolicy::FailOpen;
+    };
+    match queries::user_exists(&user_id).await {
+        Ok(true) => {}
+        Ok(false) => return FailPolicy::FailOpen,
+        Err(e) => {
+            tracing::warn!("Error checking if {user_id} has a system: {e:#}");
+            return CONFIG.fail_policy;
+        }
+    }
+    match queries::get_fail_policy(&user_id).await {
+        Ok(policy) => policy.unwrap_or(CONFIG.fail_policy),
+        Err(e) => {
+            tracing::warn!("Error getting fail policy for {user_id}: {e:#}");
+            CONFIG.fail_policy
+        }
+    }
+}
+
+/// The error returned instead of sending a message whose sender's identity couldn't be updated.
+fn identity_error(e: &anyhow::Error) -> Value {
+    serde_json::json!({
+        "errcode": "M_FORBIDDEN",
+        "error": format!(
+            "Plural Kitty couldn't set your member's name and avatar in this room, so the message \
+            wasn't sent in case it showed up as the wrong member ({e}). Try sending it again, or \
+            send `!failpolicy fail-open` to Plural Kitty to send messages anyway when this happens."
+        ),
+    })
+}
+
+/// Finds the members a message without a proxy tag should be sent as, in front order. A member
+/// set for the room takes priority over the user's autoproxy mode.
+async fn current_members(user_id: &str, room_id: &str) -> anyhow::Result<Vec<Member>> {
+    if let Some(member) = queries::get_room_fronter(user_id, room_id).await? {
+        return Ok(vec![member]);
+    }
+    match queries::get_autoproxy(user_id).await? {
+        AutoproxyMode::Front => queries::get_current_fronters(user_id)
+            .await
+            .context("Error getting user's current members"),
+        AutoproxyMode::Latch => Ok(queries::get_latched_member(user_id)
+            .await
+            .context("Error getting user's latched member")?
+            .into_iter()
+            .collect()),
+        AutoproxyMode::Off => Ok(vec![]),
+    }
+}
+
+async fn update_indentity(
+    client: &HttpClient,
+    user_id: &str,
+    room_id: String,
+    token: &str,
+    masquerading: bool,
+    tagged_member: Option<Member>,
+) -> anyhow::Result<Vec<Member>> {
+    let members = match tagged_member {
+        Some(member) => vec![member],
+        None => current_members(user_id, &room_id).await?,
+    };
+
+    let room_key = (user_id.to_owned(), room_id.clone());
+    let identity = if members.is_empty() {
+        // Only reset rooms for users that have set up Plural Kitty
+        if !queries::user_exists(user_id).await? {
+            return Ok(members);
+        }
+        if ACCOUNT_ROOMS.lock().await.contains(&room_key) {
+            return Ok(members);
+        }
+        identity::account(user_id).await?
+    } else {
+        ACCOUNT_ROOMS.lock().await.remove(&room_key);
+        identity::compose(user_id, members.clone()).await?
+    };
+
+    // ** This ensures multiple join evens aren't sent if the users sends a second message
+    // before the join event is posted.
+    let update_lock = {
+        let read_lock = UPDATE_LOCKS.read().await;
+        match read_lock.get(user_id) {
+            Some(lock) => lock.to_owned(),
+            None => {
+                drop(read_lock);
+                let mut write_lock = UPDATE_LOCKS.write().await;
+                let update_lock = Arc::new(Mutex::new(()));
+                write_lock.insert(user_id.to_owned(), update_lock.clone());
+                update_lock
+            }
+        }
+    };
+    let _lock = update_lock.lock().await;
+    // **
+    let client = matrix_sdk::ruma::Client::builder()
+        .homeserver_url(CONFIG.synapse.host.to_owned())
+        .access_token(Some(token.to_owned()))
+        .http_client(client.to_owned())
+        .await
+        .context("Error building proxy matrix client")?;
+
+    let room_id: OwnedRoomId = room_id
+        .parse()
+        .with_context(|| format!("room id {room_id} not valid room id"))?;
+    let as_user: Option<OwnedUserId> = if masquerading {
+        let as_user = user_id
+            .parse()
+            .with_context(|| format!("{user_id} isn't a valid user ID"))?;
+        Some(as_user)
+    } else {
+        None
+    };
+
+    let request = get_state_events_for_key::v3::Request::new(
+        room_id.clone(),
+        StateEventType::RoomMember,
+        user_id.to_owned(),
+    );
+    let response = match &as_user {
+        Some(as_user) => client.send_request_as(as_user, request).await,
+        None => client.send_request(request).await,
+    };
+    let mut join_event: RoomMemberEventContent = response
+        .with_context(|| format!("Error getting join event for user {user_id}"))?
+        .content
+        .deserialize_as()
+        .with_context(|| format!("Error deserializing join event for {user_id}"))?;
+
+    let mut changed = false;
+
+    match (identity.display_name, &join_event.displayname) {
+        (Some(member_name), Some(curr_name)) if member_name != *curr_name => {
+            join_event.displayname = Some(member_name);
+            changed = true;
+        }
+        (Some(member_name), None) => {
+            join_event.displayname = Some(member_name);
+            changed = true;
+        }
+        (None, Some(_)) if identity.clear_missing => {
+            join_event.displayname = None;
+            changed = true;
+        }
+        _ => {}
+    }
+    match (identity.avatar, &join_event.avatar_url) {
+        (Some(member_avatar), Some(curr_avatar)) if member_avatar != *curr_avatar => {
+            join_event.avatar_url = Some(member_avatar.into());
+            changed = true;
+        }
+        (Some(member_avatar), None) => {
+            join_event.avatar_url = Some(member_avatar.into());
+            changed = true;
+        }
+        (None, Some(_)) if identity.clear_missing => {
+            join_event.avatar_url = None;
+            changed = true;
+        }
+        _ => {}
+    }
+
+    if changed {
+        let request = send_state_event::v3::Request::new(
+            room_id,
+            user_id,
+            &AnyStateEventContent::from(join_event),
+        )
+        .with_context(|| format!("Error serializing join event for {user_id}"))?;
+        match &as_user {
+            Some(as_user) => client.send_request_as(as_user, request).await,
+            None => client.send_request(request).await,
+        }
+        .with_context(|| format!("Error sending new join event for {user_id}"))?;
+    }
+
+    if members.is_empty() {
+        let mut account_rooms = ACCOUNT_ROOMS.lock().await;
+        if account_rooms.len() >= ACCOUNT_ROOMS_CAPACITY {
+            account_rooms.clear();
+        }
+        account_rooms.insert(room_key);
+    }
+    Ok(members)
 }
 
 async fn passthrough(
@@ -190,14 +549,58 @@ async fn msg_send_handler(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     req: Request<Body>,
 ) -> Response<Body> {
-    tracing::info!(
-        "Message event handler got {room_id} {event_type} {txn_id} {}",
-        auth.token()
-    );
-    if let Err(e) = update_indentity(&state, room_id, auth).await {
-        tracing::error!("Error handling message event: {e:#}");
-    }
+    tracing::info!("Message event handler got {room_id} {event_type} {txn_id}");
+    let (mut parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Error reading message event body");
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(format!("{e:#}").into())
+                .unwrap();
+        }
+    };
+    let masquerade = match masquerade_param(parts.uri.query()) {
+        Ok(masquerade) => masquerade,
+        Err(e) => {
+            tracing::warn!("Ignoring invalid user_id parameter: {e:#}");
+            None
+        }
+    };
+    let proxied = match proxy_with_policy(
+        &state,
+        &room_id,
+        &event_type,
+        &auth,
+        masquerade.as_deref(),
+        &body,
+    )
+    .await
+    {
+        Ok(proxied) => proxied,
+        Err(e) => {
+            tracing::error!("Not sending message event: {e:#}");
+            // A 4xx so clients show the error instead of retrying the send on their own
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header(CONTENT_TYPE, "application/json")
+                .body(identity_error(&e).to_string().into())
+                .unwrap();
+        }
+    };
+    let body = match proxied.body {
+        Some(new_body) => {
+            parts.headers.insert(CONTENT_LENGTH, new_body.len().into());
+            Body::from(new_body)
+        }
+        None => Body::from(body),
+    };
+    let req = Request::from_parts(parts, body);
     match passthrough(&state.client, req).await {
+        Ok(resp) if resp.status().is_success() && !proxied.members.is_empty() => {
+            record_sender(resp, room_id, proxied.user_id, proxied.members).await
+        }
         Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Error doing pass through to matrix server");
@@ -209,6 +612,45 @@ async fn msg_send_handler(
     }
 }
 
+/// Records who a message was sent as using the event ID in synapse's response, then passes the
+/// response on unchanged.
+async fn record_sender(
+    resp: Response<Body>,
+    room_id: String,
+    user_id: String,
+    members: Vec<String>,
+) -> Response<Body> {
+    let (parts, body) = resp.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Error reading send response: {e:#}");
+            return Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(format!("{e:#}").into())
+                .unwrap();
+        }
+    };
+    match serde_json::from_slice::<Value>(&body)
+        .ok()
+        .as_ref()
+        .and_then(|v| v.get("event_id"))
+        .and_then(Value::as_str)
+    {
+        Some(event_id) => {
+            let event_id = event_id.to_owned();
+            tokio::spawn(async move {
+                if let Err(e) = queries::log_message(&event_id, &room_id, &user_id, &members).await
+                {
+                    tracing::error!("Error logging message {event_id}: {e:#}");
+                }
+            });
+        }
+        None => tracing::warn!("Send response for {user_id} in {room_id} has no event ID"),
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
 async fn passthrough_handler(State(state): State<AppState>, req: Request<Body>) -> Response<Body> {
     match passthrough(&state.client, req).await {
         Ok(resp) => resp,
@@ -221,3 +663,32 @@ async fn passthrough_handler(State(state): State<AppState>, req: Request<Body>)
         }
     }
 }
+
+/// Passes `/logout` and `/logout/all` through, then forgets the tokens they logged out.
+async fn logout_handler(
+    State(state): State<AppState>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    req: Request<Body>,
+) -> Response<Body> {
+    let all = req.uri().path().ends_with("/logout/all");
+    let token = auth.map(|TypedHeader(auth)| auth.token().to_owned());
+    // The token stops working once it is logged out, so find its user first
+    let user_id = match &token {
+        Some(token) if all => get_user_id(&state.user_ids, token).await.ok(),
+        _ => None,
+    };
+    let resp = passthrough_handler(State(state.clone()), req).await;
+    if resp.status().is_success() {
+        let mut user_ids = state.user_ids.lock().await;
+        if let Some(token) = &token {
+            user_ids.remove(token);
+            HOMESERVER.forget_token(token);
+        }
+        if let Some(user_id) = &user_id {
+            tracing::debug!("Forgetting all tokens for {user_id}");
+            user_ids.remove_user(user_id);
+            HOMESERVER.forget_user(user_id);
+        }
+    }
+    resp
+}
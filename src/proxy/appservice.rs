@@ -0,0 +1,157 @@
+//! Recognises appservices, like bridges, sending as one of their users with `?user_id=`.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context};
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Registration {
+    as_token: String,
+    sender_localpart: String,
+    #[serde(default)]
+    namespaces: Namespaces,
+}
+
+#[derive(Deserialize, Default)]
+struct Namespaces {
+    #[serde(default)]
+    users: Vec<Namespace>,
+}
+
+#[derive(Deserialize)]
+struct Namespace {
+    regex: String,
+}
+
+#[derive(Debug)]
+pub struct Appservice {
+    as_token: String,
+    /// The appservice's own user
+    sender: String,
+    users: Vec<Regex>,
+}
+
+impl Appservice {
+    /// Reads an appservice registration file.
+    pub fn from_registration(yaml: &str, server_name: &str) -> anyhow::Result<Self> {
+        let registration: Registration =
+            serde_yaml::from_str(yaml).context("Invalid appservice registration")?;
+        let users = registration
+            .namespaces
+            .users
+            .iter()
+            // Like Synapse, namespaces only have to match the start of the user ID
+            .map(|namespace| Regex::new(&format!("^(?:{})", namespace.regex)))
+            .collect::<Result<_, _>>()
+            .context("Invalid user namespace in appservice registration")?;
+        Ok(Appservice {
+            as_token: registration.as_token,
+            sender: format!("@{}:{server_name}", registration.sender_localpart),
+            users,
+        })
+    }
+
+    /// The user a request with this appservice's token is sent as, given its `user_id` parameter.
+    pub fn user_id(&self, masquerade: Option<&str>) -> anyhow::Result<String> {
+        match masquerade {
+            None => Ok(self.sender.clone()),
+            Some(user_id) if user_id == self.sender => Ok(user_id.to_owned()),
+            Some(user_id) if self.users.iter().any(|regex| regex.is_match(user_id)) => {
+                Ok(user_id.to_owned())
+            }
+            Some(user_id) => bail!(
+                "{user_id} isn't in the namespace of appservice {}",
+                self.sender
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Appservices(Vec<Appservice>);
+
+impl Appservices {
+    pub fn load(paths: &[PathBuf], server_name: &str) -> anyhow::Result<Self> {
+        paths
+            .iter()
+            .map(|path| {
+                let yaml = std::fs::read_to_string(path)
+                    .with_context(|| format!("Error reading {}", path.display()))?;
+                Appservice::from_registration(&yaml, server_name)
+                    .with_context(|| format!("Error loading {}", path.display()))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(Appservices)
+    }
+
+    /// The appservice the token belongs to, if it is one of the configured ones.
+    pub fn find(&self, token: &str) -> Option<&Appservice> {
+        self.0
+            .iter()
+            .find(|appservice| appservice.as_token == token)
+    }
+}
+
+/// Reads the `user_id` parameter appservices masquerade with from a query string.
+pub fn masquerade_param(query: Option<&str>) -> anyhow::Result<Option<String>> {
+    let Some(query) = query else {
+        return Ok(None);
+    };
+    for pair in query.split('&') {
+        if let Some(value) = pair.strip_prefix("user_id=") {
+            let value = percent_encoding::percent_decode_str(value)
+                .decode_utf8()
+                .map_err(|_| anyhow!("user_id parameter isn't valid UTF-8"))?;
+            return Ok(Some(value.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{masquerade_param, Appservice};
+
+    const REGISTRATION: &str = r#"
+id: discord
+url: http://localhost:29334
+as_token: as-secret
+hs_token: hs-secret
+sender_localpart: discordbot
+namespaces:
+  users:
+    - exclusive: true
+      regex: '@_discord_.*:test\.local'
+  aliases: []
+"#;
+
+    #[test]
+    fn registration() {
+        let appservice = Appservice::from_registration(REGISTRATION, "test.local").unwrap();
+        assert_eq!(appservice.user_id(None).unwrap(), "@discordbot:test.local");
+        assert_eq!(
+            appservice
+                .user_id(Some("@_discord_123:test.local"))
+                .unwrap(),
+            "@_discord_123:test.local"
+        );
+        assert!(appservice.user_id(Some("@test:test.local")).is_err());
+        assert!(appservice
+            .user_id(Some("@x:@_discord_1:test.local"))
+            .is_err());
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(masquerade_param(None).unwrap(), None);
+        assert_eq!(masquerade_param(Some("ts=5")).unwrap(), None);
+        assert_eq!(
+            masquerade_param(Some("ts=5&user_id=%40_discord_1%3Atest.local"))
+                .unwrap()
+                .as_deref(),
+            Some("@_discord_1:test.local")
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Works out the display name and avatar a user should have in a room.
+
+use crate::db::models::{CofrontAvatar, Member, SystemProfile};
+use crate::db::queries;
+use crate::homeserver::HOMESERVER;
+
+/// Synapse rejects display names longer than this many characters.
+pub const MAX_DISPLAY_NAME_LEN: usize = 256;
+
+/// Characters that are trimmed off the ends of a rendered template when a placeholder was empty.
+const TEMPLATE_SEPARATORS: &[char] = &['|', '-', '/', ',', '~', '·', '•'];
+
+/// The display name and avatar to set in a room. `None` leaves the room's current value alone
+/// unless `clear_missing` is set, in which case it is removed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub display_name: Option<String>,
+    pub avatar: Option<String>,
+    pub clear_missing: bool,
+}
+
+/// Builds the identity for a list of fronting members, in front order.
+///
+/// A single member is used as is. When several members are fronting their names are joined
+/// with the user's co-fronting separator and the avatar is picked by their avatar rule. If that
+/// leaves no avatar the system's avatar is used instead. If the user has a display name
+/// template the name is rendered through it.
+pub async fn compose(user_id: &str, mut members: Vec<Member>) -> anyhow::Result<Identity> {
+    let system = queries::get_system(user_id).await?;
+    let (display_name, avatar) = if members.len() == 1 {
+        let member = members.remove(0);
+        let display_name = match &system.template {
+            Some(template) => {
+                let name = member.display_name.as_deref().unwrap_or(&member.name);
+                let pronouns = member.pronouns.as_deref().or(system.pronouns.as_deref());
+                Some(apply_template(template, name, pronouns, &system))
+            }
+            None => member.display_name,
+        };
+        (display_name, member.avatar)
+    } else {
+        let settings = queries::get_cofront_settings(user_id).await?;
+        let names: Vec<&str> = members
+            .iter()
+            .map(|m| m.display_name.as_deref().unwrap_or(&m.name))
+            .collect();
+        let name = join_names(&names, &settings.separator);
+        let display_name = match &system.template {
+            Some(template) => apply_template(template, &name, system.pronouns.as_deref(), &system),
+            None => name,
+        };
+        let avatar = match settings.avatar {
+            CofrontAvatar::First => members.into_iter().find_map(|m| m.avatar),
+            CofrontAvatar::Account => {
+                let profile = HOMESERVER.profile(user_id).await?;
+                Some(profile.avatar).filter(|avatar| !avatar.is_empty())
+            }
+        };
+        (Some(display_name), avatar)
+    };
+    let avatar = avatar.or(system.avatar);
+    Ok(Identity {
+        display_name,
+        avatar,
+        clear_missing: false,
+    })
+}
+
+/// The identity of the account itself, used to put rooms back to the user's global profile
+/// when nobody is fronting.
+pub async fn account(user_id: &str) -> anyhow::Result<Identity> {
+    let profile = HOMESERVER.profile(user_id).await?;
+    Ok(Identity {
+        display_name: Some(profile.display_name).filter(|name| !name.is_empty()),
+        avatar: Some(profile.avatar).filter(|avatar| !avatar.is_empty()),
+        clear_missing: true,
+    })
+}
+
+/// Joins names with a separator, e.g. `Alex & Sam`. Separators that are normally written
+/// straight after a word, like `,`, don't get a space in front of them.
+pub fn join_names(names: &[&str], separator: &str) -> String {
+    let separator = if separator.starts_with([',', ';']) {
+        format!("{separator} ")
+    } else {
+        format!(" {separator} ")
+    };
+    names.join(&separator)
+}
+
+/// Renders `template` for `name`, falling back to the plain name if the result would be too
+/// long to set. The plain name is cut short if it is too long as well.
+fn apply_template(
+    template: &str,
+    name: &str,
+    pronouns: Option<&str>,
+    system: &SystemProfile,
+) -> String {
+    let rendered = render_template(template, name, pronouns, system);
+    if rendered.chars().count() <= MAX_DISPLAY_NAME_LEN {
+        return rendered;
+    }
+    tracing::warn!("Rendered display name for {name} is too long, using the plain name");
+    name.chars().take(MAX_DISPLAY_NAME_LEN).collect()
+}
+
+/// A piece of a display name template.
+enum Part<'a> {
+    Text(String),
+    Value(&'a str),
+    /// A placeholder for a field that isn't set
+    Empty,
+}
+
+/// Renders a display name template such as `{name} ({pronouns}) | {tag}`.
+///
+/// `{name}` is the member's name and `{pronouns}` their pronouns, falling back to the system's.
+/// `{tag}` and `{system}` come from the system profile. Unknown placeholders are left as they
+/// are. When a field isn't set its placeholder is dropped along with any brackets it leaves empty
+/// and separators it leaves dangling, so the example above renders as just `Alex` if there are no
+/// pronouns or tag. Only the template's own text is cleaned up, never the values put into it.
+pub fn render_template(
+    template: &str,
+    name: &str,
+    pronouns: Option<&str>,
+    system: &SystemProfile,
+) -> String {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        parts.push(Part::Text(rest[..start].to_owned()));
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &rest[start..=start + len];
+        let value = match placeholder {
+            "{name}" => Some(name),
+            "{pronouns}" => pronouns,
+            "{tag}" => system.tag.as_deref(),
+            "{system}" => system.name.as_deref(),
+            _ => Some(placeholder),
+        };
+        parts.push(value.map_or(Part::Empty, Part::Value));
+        rest = &rest[start + len + 1..];
+    }
+    parts.push(Part::Text(rest.to_owned()));
+
+    // Brackets around an empty placeholder
+    for i in 1..parts.len().saturating_sub(1) {
+        if !matches!(parts[i], Part::Empty) {
+            continue;
+        }
+        let (before, after) = parts.split_at_mut(i);
+        let (Part::Text(open), Part::Text(close)) = (&mut before[i - 1], &mut after[1]) else {
+            continue;
+        };
+        for (left, right) in [('(', ')'), ('[', ']')] {
+            let (trimmed_open, trimmed_close) = (open.trim_end(), close.trim_start());
+            if trimmed_open.ends_with(left) && trimmed_close.starts_with(right) {
+                *close = trimmed_close[right.len_utf8()..].to_owned();
+                open.truncate(trimmed_open.len() - left.len_utf8());
+                break;
+            }
+        }
+    }
+
+    // The text between values, and whether an empty placeholder was dropped from it
+    let mut pieces: Vec<(String, bool)> = vec![(String::new(), false)];
+    let mut values = vec![];
+    for part in parts {
+        match part {
+            Part::Text(text) => pieces.last_mut().unwrap().0 += &text,
+            Part::Empty => pieces.last_mut().unwrap().1 = true,
+            Part::Value(value) => {
+                values.push(value);
+                pieces.push((String::new(), false));
+            }
+        }
+    }
+    let last = pieces.len() - 1;
+    let mut rendered = String::with_capacity(template.len() + name.len());
+    for (i, (text, dropped)) in pieces.into_iter().enumerate() {
+        if !dropped {
+            rendered += &text;
+        } else if i == 0 || i == last {
+            let dangling = |c: char| c.is_whitespace() || TEMPLATE_SEPARATORS.contains(&c);
+            let text = if i == 0 {
+                text.trim_start_matches(dangling)
+            } else {
+                text.trim_end_matches(dangling)
+            };
+            rendered += &squeeze_whitespace(text);
+        } else {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let separators = |word: &&str| word.chars().all(|c| TEMPLATE_SEPARATORS.contains(&c));
+            match words.first() {
+                // Separators on both sides of the dropped placeholder, keep one
+                Some(first) if words.iter().all(separators) => {
+                    rendered += &format!(" {first} ");
+                }
+                _ => rendered += &squeeze_whitespace(&text),
+            }
+        }
+        if let Some(value) = values.get(i) {
+            rendered += value;
+        }
+    }
+    rendered
+}
+
+/// Turns each run of whitespace into a single space.
+fn squeeze_whitespace(text: &str) -> String {
+    let mut squeezed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.starts_with(char::is_whitespace) && !squeezed.is_empty() {
+        squeezed.insert(0, ' ');
+    }
+    if text.ends_with(char::is_whitespace) {
+        squeezed.push(' ');
+    }
+    squeezed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join_names, render_template};
+    use crate::db::models::SystemProfile;
+
+    #[test]
+    fn join() {
+        assert_eq!(join_names(&["Alex"], "&"), "Alex");
+        assert_eq!(join_names(&["Alex", "Sam"], "&"), "Alex & Sam");
+        assert_eq!(join_names(&["Alex", "Sam", "Kai"], ","), "Alex, Sam, Kai");
+        assert_eq!(join_names(&["Alex", "Sam"], "/"), "Alex / Sam");
+    }
+
+    #[test]
+    fn template() {
+        let mut system = SystemProfile {
+            tag: Some("🐱".to_owned()),
+            ..Default::default()
+        };
+        let template = "{name} ({pronouns}) | {tag}";
+        assert_eq!(
+            render_template(template, "Alex", Some("they/them"), &system),
+            "Alex (they/them) | 🐱"
+        );
+        assert_eq!(
+            render_template(template, "Alex", None, &system),
+            "Alex | 🐱"
+        );
+        system.tag = None;
+        assert_eq!(render_template(template, "Alex", None, &system), "Alex");
+        assert_eq!(
+            render_template("{name} {x}", "Alex", None, &system),
+            "Alex {x}"
+        );
+        assert_eq!(
+            render_template("{tag} {name}", "{tag}", None, &system),
+            "{tag}"
+        );
+    }
+
+    #[test]
+    fn template_keeps_values() {
+        let system = SystemProfile::default();
+        assert_eq!(
+            render_template("{tag} {name}", "~Alex~", None, &system),
+            "~Alex~"
+        );
+        assert_eq!(
+            render_template("{name} ({pronouns})", "Sam () [ ] | x", None, &system),
+            "Sam () [ ] | x"
+        );
+        assert_eq!(
+            render_template("{name}  ·  {pronouns}", "- Kai  -", Some("it/its"), &system),
+            "- Kai  -  ·  it/its"
+        );
+        assert_eq!(
+            render_template("{name} | {pronouns} | {system}", "Alex", None, &system),
+            "Alex"
+        );
+        assert_eq!(
+            render_template("{name} | {pronouns} | {name}", "Alex", None, &system),
+            "Alex | Alex"
+        );
+        assert_eq!(
+            render_template("{name} [ {pronouns} ] {name}", "Alex", None, &system),
+            "Alex Alex"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Pushes a user's new identity to their most recently active rooms right after a switch, so
+//! people reading those rooms don't have to wait for the user to speak to see the change.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+
+use crate::config::CONFIG;
+use crate::db::queries;
+use crate::homeserver::HOMESERVER;
+
+use super::{update_indentity, HttpClient};
+
+/// Shared by every rollout.
+static CLIENT: Lazy<HttpClient> = Lazy::new(HttpClient::new);
+
+/// The latest rollout started for each user that is still running. A rollout stops early once a
+/// newer one starts.
+static GENERATIONS: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(Default::default);
+
+/// Updates the user's join event in their most recently active rooms, waiting between rooms
+/// so a switch doesn't flood the homeserver. Returns the rooms that couldn't be updated.
+pub async fn rollout(user_id: &str) -> anyhow::Result<Vec<(String, anyhow::Error)>> {
+    if CONFIG.bot.rollout_rooms <= 0 {
+        return Ok(vec![]);
+    }
+    let generation = {
+        let mut generations = GENERATIONS.lock().await;
+        let generation = generations.entry(user_id.to_owned()).or_default();
+        *generation += 1;
+        *generation
+    };
+    let res = rollout_rooms(user_id, generation).await;
+    let mut generations = GENERATIONS.lock().await;
+    if generations.get(user_id) == Some(&generation) {
+        generations.remove(user_id);
+    }
+    res
+}
+
+async fn rollout_rooms(
+    user_id: &str,
+    generation: u64,
+) -> anyhow::Result<Vec<(String, anyhow::Error)>> {
+    let rooms = queries::get_active_rooms(user_id, CONFIG.bot.rollout_rooms)
+        .await
+        .context("Error getting recently active rooms")?;
+    if rooms.is_empty() {
+        return Ok(vec![]);
+    }
+    let Some(token) = HOMESERVER.access_token(user_id).await? else {
+        tracing::debug!("No access token for {user_id} to roll out with");
+        return Ok(vec![]);
+    };
+    let mut errs = vec![];
+    for (i, room_id) in rooms.into_iter().enumerate() {
+        if i > 0 {
+            tokio::time::sleep(Duration::from_millis(CONFIG.bot.rollout_delay_ms)).await;
+        }
+        if GENERATIONS.lock().await.get(user_id) != Some(&generation) {
+            tracing::debug!("Rollout for {user_id} superseded by a newer switch");
+            break;
+        }
+        tracing::debug!("Rolling out identity for {user_id} to {room_id}");
+        if let Err(e) =
+            update_indentity(&CLIENT, user_id, room_id.clone(), &token, false, None).await
+        {
+            errs.push((room_id, e));
+        }
+    }
+    Ok(errs)
+}
@@ -0,0 +1,225 @@
+//! PluralKit style proxy tags.
+//!
+//! Tags are stored on a member as a single string where the word `text` stands in for the
+//! message, e.g. `s:text` or `[text]`. Everything before the last `text` is the prefix and
+//! everything after it is the suffix, so prefixes like `context:` work. Whitespace around the
+//! message is not significant, so `s:text` and `s: text` behave the same.
+//!
+//! Tags can only be read from plain `m.room.message` events. In encrypted rooms clients send
+//! `m.room.encrypted` events whose body is ciphertext, so the proxy never sees the message text
+//! and tags are left as they are. Members still have to be switched with activators there.
+
+use serde_json::{Map, Value};
+
+use crate::db::models::Member;
+
+const PROXYABLE_MSGTYPES: &[&str] = &["m.text", "m.emote", "m.notice"];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ProxyTag<'a> {
+    pub prefix: &'a str,
+    pub suffix: &'a str,
+}
+
+impl<'a> ProxyTag<'a> {
+    pub fn parse(tag: &'a str) -> Option<Self> {
+        let (prefix, suffix) = tag.rsplit_once("text")?;
+        let (prefix, suffix) = (prefix.trim(), suffix.trim());
+        if prefix.is_empty() && suffix.is_empty() {
+            None
+        } else {
+            Some(ProxyTag { prefix, suffix })
+        }
+    }
+
+    /// Returns the message inside the tag if `text` is wrapped in it.
+    pub fn strip<'b>(&self, text: &'b str) -> Option<&'b str> {
+        let inner = text
+            .trim()
+            .strip_prefix(self.prefix)?
+            .strip_suffix(self.suffix)?
+            .trim();
+        (!inner.is_empty()).then_some(inner)
+    }
+
+    fn len(&self) -> usize {
+        self.prefix.len() + self.suffix.len()
+    }
+}
+
+/// Looks for one of `members`' proxy tags in the content of an `m.room.message` event.
+///
+/// If a tag matches it is stripped from the content in place and the member it belongs to is
+/// returned. When several tags match the longest one wins, so `[[text]]` beats `[text]`.
+pub fn strip_tags<'m>(
+    members: &'m [Member],
+    content: &mut Map<String, Value>,
+) -> Option<&'m Member> {
+    let msgtype = content.get("msgtype").and_then(Value::as_str);
+    if !msgtype.is_some_and(|t| PROXYABLE_MSGTYPES.contains(&t)) {
+        return None;
+    }
+    // Edits repeat the message in `m.new_content`, rewriting only half of it would be worse than
+    // leaving the tag in place.
+    if content.contains_key("m.new_content") {
+        return None;
+    }
+    let body = content.get("body")?.as_str()?.to_owned();
+    let (fallback, text) = split_reply_fallback(&body);
+    let (member, tag) = members
+        .iter()
+        .flat_map(|m| {
+            m.proxy_tags
+                .iter()
+                .filter_map(|t| ProxyTag::parse(t))
+                .map(move |t| (m, t))
+        })
+        .filter(|(_, tag)| tag.strip(text).is_some())
+        .max_by_key(|(_, tag)| tag.len())?;
+    content.insert(
+        "body".to_owned(),
+        format!("{fallback}{}", tag.strip(text)?).into(),
+    );
+
+    if let Some(formatted) = content.get("formatted_body").and_then(Value::as_str) {
+        let (fallback, html) = split_html_reply_fallback(formatted);
+        let prefix = html_escape::encode_text(tag.prefix);
+        let suffix = html_escape::encode_text(tag.suffix);
+        let html_tag = ProxyTag {
+            prefix: &prefix,
+            suffix: &suffix,
+        };
+        match html_tag.strip(html) {
+            Some(inner) => {
+                let formatted = format!("{fallback}{inner}");
+                content.insert("formatted_body".to_owned(), formatted.into());
+            }
+            None => {
+                // The tag was mangled by the formatting, fall back to the plain body
+                content.remove("formatted_body");
+                content.remove("format");
+            }
+        }
+    }
+    Some(member)
+}
+
+/// Splits the `> <@user:server> quoted text` reply fallback off of a message body.
+fn split_reply_fallback(body: &str) -> (&str, &str) {
+    if body.starts_with("> ") {
+        if let Some(end) = body.find("\n\n") {
+            return body.split_at(end + 2);
+        }
+    }
+    ("", body)
+}
+
+/// Splits the `<mx-reply>` reply fallback off of a formatted message body.
+fn split_html_reply_fallback(body: &str) -> (&str, &str) {
+    const END_TAG: &str = "</mx-reply>";
+    if body.starts_with("<mx-reply>") {
+        if let Some(end) = body.find(END_TAG) {
+            return body.split_at(end + END_TAG.len());
+        }
+    }
+    ("", body)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use super::{strip_tags, ProxyTag};
+    use crate::db::models::Member;
+
+    fn member(name: &str, tags: &[&str]) -> Member {
+        Member {
+            mxid: "@test:test.local".to_owned(),
+            name: name.to_owned(),
+            display_name: None,
+            avatar: None,
+            activators: vec![],
+            track_account: false,
+            proxy_tags: tags.iter().map(|t| t.to_string()).collect(),
+            pronouns: None,
+            description: None,
+            color: None,
+            birthday: None,
+            notes: None,
+            private: false,
+            private_fields: vec![],
+        }
+    }
+
+    fn content(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_tags() {
+        assert_eq!(
+            ProxyTag::parse("s: text"),
+            Some(ProxyTag {
+                prefix: "s:",
+                suffix: ""
+            })
+        );
+        assert_eq!(
+            ProxyTag::parse("[text]"),
+            Some(ProxyTag {
+                prefix: "[",
+                suffix: "]"
+            })
+        );
+        assert_eq!(
+            ProxyTag::parse("context:text"),
+            Some(ProxyTag {
+                prefix: "context:",
+                suffix: ""
+            })
+        );
+        assert_eq!(ProxyTag::parse("text"), None);
+        assert_eq!(ProxyTag::parse("s:"), None);
+    }
+
+    #[test]
+    fn longest_tag_wins() {
+        let members = [member("sasha", &["[text]"]), member("ursa", &["[[text]]"])];
+        let mut msg = content(json!({"msgtype": "m.text", "body": "[[hello]]"}));
+        let found = strip_tags(&members, &mut msg).map(|m| m.name.as_str());
+        assert_eq!(found, Some("ursa"));
+        assert_eq!(msg["body"], "hello");
+    }
+
+    #[test]
+    fn no_match_leaves_content() {
+        let members = [member("sasha", &["s:text"])];
+        let mut msg = content(json!({"msgtype": "m.text", "body": "hello s:"}));
+        assert!(strip_tags(&members, &mut msg).is_none());
+        assert_eq!(msg["body"], "hello s:");
+        let mut msg = content(json!({"msgtype": "m.text", "body": "s:   "}));
+        assert!(strip_tags(&members, &mut msg).is_none());
+        let mut msg = content(json!({"msgtype": "m.image", "body": "s:cat.png"}));
+        assert!(strip_tags(&members, &mut msg).is_none());
+    }
+
+    #[test]
+    fn keeps_reply_fallback() {
+        let members = [member("sasha", &["<text>"])];
+        let mut msg = content(json!({
+            "msgtype": "m.text",
+            "body": "> <@a:test.local> hi\n\n<hello>",
+            "format": "org.matrix.custom.html",
+            "formatted_body": "<mx-reply><blockquote>hi</blockquote></mx-reply>&lt;<em>hello</em>&gt;",
+        }));
+        assert!(strip_tags(&members, &mut msg).is_some());
+        assert_eq!(msg["body"], "> <@a:test.local> hi\n\nhello");
+        assert_eq!(
+            msg["formatted_body"],
+            "<mx-reply><blockquote>hi</blockquote></mx-reply><em>hello</em>"
+        );
+    }
+}
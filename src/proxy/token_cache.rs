@@ -0,0 +1,165 @@
+//! Remembers who access tokens belong to so the homeserver isn't asked on every message.
+//!
+//! Tokens are stored as SHA-256 hashes, entries are forgotten after [`TOKEN_TTL`] or once the
+//! token expires, and the least recently used entry makes room for new ones when the cache is
+//! full.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::homeserver::TokenOwner;
+
+/// How many tokens are remembered at once.
+pub const TOKEN_CAPACITY: usize = 10_000;
+/// How long a token is trusted before the homeserver is asked about it again.
+pub const TOKEN_TTL: Duration = Duration::from_secs(10 * 60);
+
+type TokenHash = [u8; 32];
+
+#[derive(Debug)]
+struct Entry {
+    owner: TokenOwner,
+    cached_at: Instant,
+    /// Position in [`TokenCache::recent`]
+    last_used: u64,
+}
+
+#[derive(Debug)]
+pub struct TokenCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<TokenHash, Entry>,
+    /// Entries by when they were last used, oldest first
+    recent: BTreeMap<u64, TokenHash>,
+    tick: u64,
+}
+
+fn hash(token: &str) -> TokenHash {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+impl TokenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        TokenCache {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            recent: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// The user the token belongs to, if it is cached and still fresh.
+    pub fn get(&mut self, token: &str, now: Instant) -> Option<String> {
+        let key = hash(token);
+        let entry = self.entries.get(&key)?;
+        if now.duration_since(entry.cached_at) >= self.ttl || entry.owner.expired(SystemTime::now())
+        {
+            self.remove_key(&key);
+            return None;
+        }
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(&key)?;
+        self.recent.remove(&entry.last_used);
+        self.recent.insert(tick, key);
+        entry.last_used = tick;
+        Some(entry.owner.user_id.clone())
+    }
+
+    pub fn insert(&mut self, token: &str, owner: TokenOwner, now: Instant) {
+        let key = hash(token);
+        self.remove_key(&key);
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.recent.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        let tick = self.next_tick();
+        self.recent.insert(tick, key);
+        self.entries.insert(
+            key,
+            Entry {
+                owner,
+                cached_at: now,
+                last_used: tick,
+            },
+        );
+    }
+
+    /// Forgets a token, e.g. after it is logged out. Returns who it belonged to if it was cached.
+    pub fn remove(&mut self, token: &str) -> Option<String> {
+        self.remove_key(&hash(token))
+    }
+
+    /// Forgets every token belonging to the user.
+    pub fn remove_user(&mut self, user_id: &str) {
+        let keys: Vec<TokenHash> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.owner.user_id == user_id)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.remove_key(&key);
+        }
+    }
+
+    fn remove_key(&mut self, key: &TokenHash) -> Option<String> {
+        let entry = self.entries.remove(key)?;
+        self.recent.remove(&entry.last_used);
+        Some(entry.owner.user_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use super::TokenCache;
+    use crate::homeserver::TokenOwner;
+
+    #[test]
+    fn least_recently_used_evicted() {
+        let mut cache = TokenCache::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        cache.insert("a", TokenOwner::without_expiry("@a:test.local"), now);
+        cache.insert("b", TokenOwner::without_expiry("@b:test.local"), now);
+        assert_eq!(cache.get("a", now).as_deref(), Some("@a:test.local"));
+        cache.insert("c", TokenOwner::without_expiry("@c:test.local"), now);
+        assert_eq!(cache.get("b", now), None);
+        assert_eq!(cache.get("a", now).as_deref(), Some("@a:test.local"));
+        assert_eq!(cache.get("c", now).as_deref(), Some("@c:test.local"));
+    }
+
+    #[test]
+    fn expiry_and_logout() {
+        let mut cache = TokenCache::new(10, Duration::from_secs(60));
+        let now = Instant::now();
+        cache.insert("a", TokenOwner::without_expiry("@a:test.local"), now);
+        assert_eq!(cache.get("a", now + Duration::from_secs(60)), None);
+
+        let expired = TokenOwner {
+            user_id: "@a:test.local".to_owned(),
+            expires_at: Some(SystemTime::now() - Duration::from_secs(1)),
+        };
+        cache.insert("a", expired, now);
+        assert_eq!(cache.get("a", now), None);
+
+        cache.insert("a", TokenOwner::without_expiry("@a:test.local"), now);
+        cache.insert("a2", TokenOwner::without_expiry("@a:test.local"), now);
+        cache.insert("b", TokenOwner::without_expiry("@b:test.local"), now);
+        assert_eq!(cache.remove("a").as_deref(), Some("@a:test.local"));
+        assert_eq!(cache.get("a2", now).as_deref(), Some("@a:test.local"));
+        cache.remove_user("@a:test.local");
+        assert_eq!(cache.get("a2", now), None);
+        assert_eq!(cache.get("b", now).as_deref(), Some("@b:test.local"));
+    }
+}
@@ -0,0 +1,15 @@
+//! Stand-ins for the HTTP APIs Plural Kitty talks to, for tests.
+
+use axum::Router;
+
+/// Serves `app` on a free local port and returns its base URL, ending in `/`.
+pub fn serve(app: Router) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+    format!("http://{addr}/")
+}